@@ -1,160 +1,83 @@
 use anyhow::{Context, Result};
 use clap::Parser;
 use colored::*;
-use slinky::{cli::SlinkyLnCli, create_hard_link, create_hard_link_tree, create_symlink_tree, log_link};
+use slinky::{
+    EXIT_FATAL, Summary,
+    cli::SlinkyLnCli,
+    ln::{BatchEntry, Kind, LinkSpec},
+    log_link,
+};
 use std::fs;
-use std::os::unix::fs::symlink;
-use std::path::{Path, PathBuf};
+use std::io;
+use std::path::Path;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = SlinkyLnCli::parse();
-
-    // read target string from CLI input
-    let raw_target_string = &cli.target;
-
-    // dereference target string if necessary
-    let (base_target_path, base_target_string) = if cli.dereference {
-        let resolved_path = dereference_symlink(Path::new(raw_target_string));
-        let resolved_string = resolved_path.to_string_lossy().to_string();
-        (resolved_path, resolved_string)
-    } else {
-        (PathBuf::from(raw_target_string), raw_target_string.clone())
-    };
-
-    // determine where the new link will be created (the 'origin')
-    let origin_input = cli.origin.as_deref().map(Path::new).unwrap_or(Path::new("."));
-    let origin_path_buf;
-    let origin_path = if origin_input.is_dir() {
-        let resolved_target = if base_target_path.exists() {
-            fs::canonicalize(&base_target_path)?
-        } else {
-            base_target_path.clone()
-        };
-        let file_name = resolved_target
-            .file_name()
-            .context("Could not get basename; target path terminates in ..")?;
-        origin_path_buf = origin_input.join(file_name);
-        &origin_path_buf
-    } else {
-        origin_input
-    };
-
-    let target_exists = base_target_path.exists();
-
-    if !target_exists {
-        if cli.tree || cli.hard {
-            anyhow::bail!("Target does not exist; cannot create {}", if cli.tree { "tree" } else { "hardlink" });
-        } else if !cli.allow_dangling {
-            anyhow::bail!("Target does not exist; refusing to create dangling symlink without --allow-dangling");
+    match run(&cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}: {:#}", "Error".red(), e);
+            ExitCode::from(EXIT_FATAL)
         }
     }
+}
 
-    if cli.force && origin_path.exists() {
-        if cli.verbose {
-            println!("{}: {}", "remove existing file".bold().red(), origin_path.display());
-        }
-        if !cli.dry_run {
-            fs::remove_file(origin_path)?;
-        }
+fn run(cli: &SlinkyLnCli) -> Result<ExitCode> {
+    if let Some(batch) = &cli.batch {
+        return make_batch(cli, batch);
+    }
+    make_link(cli)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Make the one link `cli` describes.
+fn make_link(cli: &SlinkyLnCli) -> Result<()> {
+    let spec = LinkSpec::from_cli(cli, Path::new(""))?;
+
+    let replace_existing = cli.force && fs::symlink_metadata(&spec.origin).is_ok();
+    if replace_existing && cli.verbose {
+        println!("{}: {}", "replace existing file".bold().red(), spec.origin.display());
     }
 
-    // which type of link are we creating?
-    if cli.tree {
-        if cli.hard {
-            if cli.verbose {
-                let label = "create hardlink tree";
-                log_link(
-                    Some(label.bold()),
-                    &origin_path.display().to_string(),
-                    &raw_target_string,
-                );
-            }
-            if !cli.dry_run {
-                create_hard_link_tree(&base_target_path, origin_path)?;
-            }
-        } else {
-            if cli.verbose {
-                let label = "create symlink tree";
-                log_link(
-                    Some(label.bold()),
-                    &origin_path.display().to_string(),
-                    &raw_target_string,
-                );
-            }
-            if !cli.dry_run {
-                create_symlink_tree(&base_target_path, origin_path)?;
-            }
-        }
-    } else if cli.hard {
-        if cli.verbose {
-            let label = "create hardlink";
-            log_link(
-                Some(label.bold()),
-                &origin_path.display().to_string(),
-                &raw_target_string,
-            );
-        }
-        if !cli.dry_run {
-            create_hard_link(&base_target_path, origin_path)?;
-        }
-    } else {
-        // transform target string for --relative and --absolute if necessary
-        let target_contents = if cli.absolute {
-            fs::canonicalize(&base_target_path)?
-                .to_string_lossy()
-                .to_string()
-        } else if cli.relative {
-            let abs_target = fs::canonicalize(&base_target_path)?;
-            let origin_parent = origin_path
-                .parent()
-                .filter(|p| !p.as_os_str().is_empty())
-                .unwrap_or_else(|| Path::new("."));
-            let abs_origin_parent = fs::canonicalize(origin_parent)?;
-            pathdiff::diff_paths(&abs_target, &abs_origin_parent)
-                .context("Failed to calculate relative path")?
-                .to_string_lossy()
-                .to_string()
-        } else {
-            base_target_string
+    if cli.verbose {
+        // hardlinks and trees are described by the target as given
+        let target = match spec.kind {
+            Kind::Symlink => spec.target.to_string_lossy().to_string(),
+            _ => cli.target.clone().unwrap_or_default(),
         };
-        // create the symlink
-        if cli.verbose {
-            log_link(
-                Some("create symlink".bold()),
-                &origin_path.display().to_string(),
-                &target_contents,
-            );
-        }
-        if !cli.dry_run {
-            symlink(&target_contents, origin_path)?;
-        }
+        let label = format!("create {}", spec.kind);
+        log_link(Some(label.bold()), &spec.origin.display().to_string(), &target);
+    }
+    if !cli.dry_run {
+        spec.create(replace_existing)?;
     }
 
     Ok(())
 }
 
-fn dereference_symlink(path: &Path) -> PathBuf {
-    if !path.is_symlink() {
-        return path.to_path_buf();
-    }
-    match fs::canonicalize(path) {
-        Ok(resolved) => resolved,
-        Err(_) => {
-            let mut current = path.to_path_buf();
-            while current.is_symlink() {
-                if let Ok(next) = fs::read_link(&current) {
-                    if next.is_absolute() {
-                        current = next;
-                    } else if let Some(parent) = current.parent() {
-                        current = parent.join(next);
-                    } else {
-                        current = next;
-                    }
-                } else {
-                    break;
-                }
-            }
-            current
+/// Make every link listed in `path`, or on stdin if it is `-`, carrying on
+/// past the ones that fail.
+fn make_batch(cli: &SlinkyLnCli, path: &Path) -> Result<ExitCode> {
+    let input = if path == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(path).with_context(|| format!("{}: cannot read batch", path.display()))?
+    };
+    let unit = if cli.null { "pair" } else { "line" };
+
+    let mut summary = Summary::default();
+    for entry in BatchEntry::parse_all(&input, cli.null) {
+        let made = summary.handle_operation(|| {
+            entry
+                .to_cli(cli)
+                .and_then(|line| make_link(&line))
+                .map_err(|e| anyhow::anyhow!("{} {}: {:#}", unit, entry.number, e))
+        });
+        if made {
+            summary.done += 1;
         }
     }
+    summary.log("slinky-ln", if cli.dry_run { "planned" } else { "created" });
+    Ok(summary.exit_code())
 }
@@ -1,361 +1,868 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use clap::Parser;
 use colored::*;
 use regex::Regex;
 use slinky::{
-    cli::{SlinkyCli, SlinkyCommand},
-    create_hard_link, create_hard_link_tree, create_symlink_tree, handle_operation, log_dangling_link,
-    log_link, log_link_err, log_transformation,
+    Action, Chain, Change, Confirm, EXIT_FATAL, Excludes, Journal, Link, LinkScanner, Outcome, Plan, Skip, Status,
+    Stow, Summary,
+    cli::{SlinkyCli, SlinkyCommand, StowArgs},
+    journal, log_chain, log_change, log_link, log_diff, log_link_err, log_op, log_skip, log_warning,
+    ln::{Kind, LinkSpec},
+    manifest::Manifest,
+    output::{Printer, Record},
+    parallel,
+    repair::Index,
+    replace_symlink,
+    snapshot::Snapshot,
+    tidy_path, transform,
 };
+use std::io::{self, BufRead, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{lchown, symlink};
 use std::fs;
-use std::os::unix::fs::symlink;
-use std::path::Path;
-use std::process::Command;
+use std::path::{self, Path, PathBuf};
+use std::process::{Command, ExitCode};
 use walkdir::WalkDir;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = SlinkyCli::parse();
+    match run(&cli) {
+        Ok(summary) => summary.exit_code(),
+        Err(e) => {
+            eprintln!("{}: {:#}", "Error".red(), e);
+            ExitCode::from(EXIT_FATAL)
+        }
+    }
+}
 
-    if !cli.path.exists() {
-        anyhow::bail!("{}: No such file or directory", cli.path.display());
+fn run(cli: &SlinkyCli) -> Result<Summary> {
+    match &cli.command {
+        SlinkyCommand::Apply { plan } => return apply_plan(cli, plan),
+        SlinkyCommand::Undo { last, journal } => return undo(cli, *last, journal.as_deref()),
+        SlinkyCommand::Stow(args) | SlinkyCommand::Unstow(args) | SlinkyCommand::Restow(args) => {
+            return stow(cli, args);
+        }
+        SlinkyCommand::Sync { manifest, prune } => return sync(cli, manifest, *prune),
+        SlinkyCommand::Restore { file, into, force } => return restore(cli, file, into, *force),
+        SlinkyCommand::Diff { old, new, exit_code } => return diff(cli, old, new, *exit_code),
+        _ => {}
     }
 
-    let origin_filter_re = cli
-        .filter_origin
-        .as_ref()
-        .map(|p| Regex::new(p))
-        .transpose()?;
-    let target_filter_re = cli
-        .filter_target
-        .as_ref()
-        .map(|p| Regex::new(p))
-        .transpose()?;
-
-    let mut walker = WalkDir::new(&cli.path).follow_links(false);
-    if let Some(depth) = cli.max_depth {
-        walker = walker.max_depth(depth);
+    if cli.save_plan.is_some() && (cli.command.is_report() || matches!(cli.command, SlinkyCommand::Exec { .. })) {
+        anyhow::bail!("--save-plan cannot be used with {}", cli.command);
     }
 
-    for entry in walker.into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if !path.is_symlink() {
-            continue;
+    let mut snapshot = match (&cli.command, cli.paths.as_slice()) {
+        (SlinkyCommand::Snapshot { .. }, _) if cli.from_stdin0 => {
+            anyhow::bail!("{} cannot be used with --from-stdin0", cli.command)
         }
+        (SlinkyCommand::Snapshot { .. }, [root]) => Some(Snapshot::new(root)?),
+        (SlinkyCommand::Snapshot { .. }, _) => anyhow::bail!("{} takes a single search root", cli.command),
+        _ => None,
+    };
 
-        let target_path = fs::read_link(path)?;
-        let link_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let scanner = LinkScanner::from_cli(cli)?;
+
+    if let SlinkyCommand::ToRelative {
+        within: Some(dir), ..
+    } = &cli.command
+        && !dir.is_dir()
+    {
+        anyhow::bail!("{}: not a directory", dir.display());
+    }
+
+    if let SlinkyCommand::Repair { choose: true, .. } = &cli.command
+        && cli.from_stdin0
+    {
+        anyhow::bail!("--choose cannot be used with --from-stdin0");
+    }
 
-        let target_str = target_path.to_string_lossy(); // for verbose messages
+    let edit_re = match &cli.command {
+        SlinkyCommand::EditTarget { pattern, .. } => Some(Regex::new(pattern)?),
+        _ => None,
+    };
+    let index = match &cli.command {
+        SlinkyCommand::Repair { search, .. } => Some(Index::build(search, &Excludes::from_cli(cli)?)?),
+        _ => None,
+    };
 
-        let target_resolved = if target_path.is_absolute() {
-            target_path.clone()
+    let cmd_name = cli.command.to_string(); // for verbose messages
+
+    let mut plan = Plan::default();
+    let mut printer = Printer::new(cli.format);
+    let mut summary = Summary::default();
+    let mut confirm = if cli.command.is_report() { None } else { open_confirm(cli) };
+
+    // planning may canonicalize targets, so with --jobs it runs on the pool too
+    let transforming = !cli.command.is_report() && !matches!(cli.command, SlinkyCommand::Exec { .. });
+    let plan_one = |link: Result<Link>| -> Result<(Link, Outcome)> {
+        let link = link?;
+        let outcome = if transforming {
+            plan_link(cli, edit_re.as_ref(), index.as_ref(), &link)?
         } else {
-            link_dir.join(&target_path)
+            Outcome::Unchanged
         };
+        Ok((link, outcome))
+    };
+    let links = scanner.scan();
+    let planned: Box<dyn Iterator<Item = Result<(Link, Outcome)>>> = match cli.jobs {
+        Some(jobs) if transforming => Box::new(parallel::map(links.collect(), jobs, plan_one).into_iter()),
+        _ => Box::new(links.map(&plan_one)),
+    };
 
-        let is_dangling = !target_resolved.exists();
-        let is_absolute = target_path.is_absolute();
-
-        // Filters
-        if cli.only_dangling && !is_dangling {
-            continue;
-        }
-        if cli.only_attached && is_dangling {
-            continue;
-        }
-        if cli.only_absolute && !is_absolute {
-            continue;
-        }
-        if cli.only_relative && is_absolute {
-            continue;
-        }
-        if let Some(re) = &origin_filter_re
-            && !re.is_match(&path.to_string_lossy())
-        {
-            continue;
-        }
-        if let Some(re) = &target_filter_re
-            && !re.is_match(&target_path.to_string_lossy())
-        {
-            continue;
+    for planned in planned {
+        if confirm.as_ref().is_some_and(Confirm::has_quit) {
+            break;
         }
+        let (link, outcome) = match planned {
+            Ok(planned) => planned,
+            Err(e) => {
+                summary.handle_operation(|| Err(e));
+                continue;
+            }
+        };
 
-        let cmd_name = cli.command.to_string(); // for verbose messages
+        // ties repair can't break are the user's to settle
+        let outcome = match (&cli.command, outcome, &index) {
+            (SlinkyCommand::Repair { choose: true, .. }, Outcome::Skip(Skip::Ambiguous), Some(index)) => {
+                match choose_candidate(&cmd_name, &link, &index.candidates(&link))? {
+                    Some(found) => transform::retarget(&link, &found)?,
+                    None => Outcome::Skip(Skip::Declined),
+                }
+            }
+            (_, outcome, _) => outcome,
+        };
 
         match cli.command {
+            SlinkyCommand::List { .. } if printer.is_structured() => {
+                printer.emit(Record::new(&link, &cmd_name))?;
+                summary.done += 1;
+            }
+
             SlinkyCommand::List {
                 status,
                 origin_only,
+                null,
             } => {
-                if origin_only {
-                    println!("{}", path.display());
+                if null {
+                    let mut out = io::stdout().lock();
+                    out.write_all(link.origin.as_os_str().as_bytes())?;
+                    out.write_all(b"\0")?;
+                } else if origin_only {
+                    println!("{}", link.origin.display());
                 } else {
-                    let prefix = if status {
-                        Some(if is_dangling {
-                            "dangling".red()
-                        } else {
-                            "attached".green()
-                        })
-                    } else {
-                        None
-                    };
-                    log_link(
-                        prefix,
-                        &path.display().to_string(),
-                        &target_path.to_string_lossy(),
-                    );
+                    let prefix = status.then(|| {
+                        let status = link.status.to_string();
+                        match link.status {
+                            Status::Attached => status.green(),
+                            Status::Denied => status.yellow(),
+                            _ => status.red(),
+                        }
+                    });
+                    log_link(prefix, &link.origin_str(), &link.target_str());
                 }
+                summary.done += 1;
             }
 
-            SlinkyCommand::Tidy => {
-                handle_operation(|| {
-                    let cleaned = slinky::tidy_path(&target_path);
-
-                    let new_target_str = cleaned.to_string_lossy();
-                    if new_target_str != target_str {
-                        if cli.verbose {
-                            log_transformation(
-                                &cmd_name,
-                                &path.to_string_lossy(),
-                                &target_str,
-                                &new_target_str,
-                            );
-                        }
-                        if !cli.dry_run {
-                            fs::remove_file(path)?;
-                            symlink(cleaned, path)?;
-                        }
-                    } else {
-                        log_link_err(
-                            Some(cmd_name.bold()),
-                            Some("target is already tidy".green()),
-                            &path.to_string_lossy(),
-                            &target_str,
-                        );
-                    }
-                    Ok(())
-                });
+            SlinkyCommand::Resolve => {
+                let chain = Chain::follow(&link.origin, &link.target, cli.max_hops);
+                if printer.is_structured() {
+                    printer.emit(Record::new(&link, &cmd_name).with_hops(chain.hops))?;
+                } else {
+                    log_chain(&chain);
+                }
+                summary.done += 1;
             }
 
-            SlinkyCommand::EditTarget {
-                ref pattern,
-                ref replace,
-                replace_all,
-            } => {
-                let re = Regex::new(&pattern)?;
-                if re.is_match(&target_str) {
-                    handle_operation(|| {
-                        let new_target_str = if replace_all {
-                            re.replace_all(&target_str, replace).into_owned()
-                        } else {
-                            re.replace(&target_str, replace).into_owned()
-                        };
-                        if new_target_str != target_str {
-                            if cli.verbose {
-                                log_transformation(
-                                    &cmd_name,
-                                    &path.to_string_lossy(),
-                                    &target_str,
-                                    &new_target_str,
-                                );
-                            }
-                            if !cli.dry_run {
-                                fs::remove_file(path)?;
-                                symlink(new_target_str, path)?;
-                            }
-                        } else {
-                            log_link_err(
-                                Some(cmd_name.bold()),
-                                Some("new target is identical to old target".red()),
-                                &path.to_string_lossy(),
-                                &target_str,
-                            );
-                        }
-                        Ok(())
-                    });
+            SlinkyCommand::Snapshot { metadata, .. } => {
+                let snapshot = snapshot.as_mut().expect("snapshot is started up front");
+                if summary.handle_operation(|| snapshot.push(&link, metadata)) {
+                    summary.done += 1;
                 }
             }
 
-            SlinkyCommand::ToAbsolute => {
-                handle_operation(|| {
-                    if is_dangling {
-                        log_dangling_link(&cmd_name, &path.to_string_lossy(), &target_str);
-                    } else if !target_path.is_absolute() {
-                        // Use canonicalize to resolve the true absolute path
-                        let abs_target = fs::canonicalize(&target_resolved).context(format!(
-                            "Failed to resolve absolute path for {}",
-                            path.display()
-                        ))?;
-                        if cli.verbose {
-                            let new_target_str = abs_target.to_string_lossy();
-                            log_transformation(
-                                &cmd_name,
-                                &path.to_string_lossy(),
-                                &target_str,
-                                &new_target_str,
+            SlinkyCommand::Exec { ref cmd_string } => {
+                let describe = || log_link(Some(cmd_string.blue()), &link.origin_str(), &link.target_str());
+                if !confirmed(confirm.as_mut(), describe)? {
+                    summary.skipped += 1;
+                    continue;
+                }
+                let ok = summary.handle_operation(|| {
+                    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+                    if cli.verbose {
+                        if printer.is_structured() {
+                            printer.emit(Record::new(&link, &cmd_name))?;
+                        } else {
+                            println!(
+                                "{}: {} {} {}",
+                                cmd_name.bold(),
+                                cmd_string.blue(),
+                                link.origin_str().cyan(),
+                                link.target_str().yellow(),
                             );
                         }
-                        if !cli.dry_run {
-                            fs::remove_file(path)?;
-                            symlink(abs_target, path)?;
-                        }
                     }
-                    Ok(())
-                });
-            }
-
-            SlinkyCommand::ToRelative => {
-                handle_operation(|| {
-                    if is_dangling {
-                        log_dangling_link(&cmd_name, &path.to_string_lossy(), &target_str);
-                    } else if target_path.is_absolute() {
-                        // Resolve the target and the link's parent to find the relative difference
-                        let abs_target = fs::canonicalize(&target_resolved)?;
-                        let abs_link_dir = fs::canonicalize(link_dir)?;
-
-                        if let Some(rel_target) = pathdiff::diff_paths(&abs_target, &abs_link_dir) {
-                            let new_target_str = rel_target.to_string_lossy();
-                            if cli.verbose {
-                                log_transformation(
-                                    &cmd_name,
-                                    &path.to_string_lossy(),
-                                    &target_str,
-                                    &new_target_str,
-                                );
-                            }
-                            if !cli.dry_run {
-                                fs::remove_file(path)?;
-                                symlink(rel_target, path)?;
-                            }
+                    if !cli.dry_run {
+                        let status = Command::new(shell)
+                            .arg("-c")
+                            .arg(cmd_string)
+                            .arg("--")
+                            .arg(&link.origin)
+                            .arg(&link.target)
+                            .status()?;
+                        if !status.success() {
+                            anyhow::bail!("{}: command {}", link.origin.display(), status);
                         }
                     }
                     Ok(())
                 });
+                if ok {
+                    summary.done += 1;
+                }
             }
 
-            SlinkyCommand::ToHardlink => {
-                handle_operation(|| {
-                    if is_dangling {
-                        log_dangling_link(&cmd_name, &path.to_string_lossy(), &target_str);
-                    } else if target_resolved.is_dir() {
-                        log_link_err(
-                            Some(cmd_name.bold()),
-                            Some("skipping directory".red()),
-                            &path.to_string_lossy(),
-                            &target_str,
-                        );
-                    } else {
-                        if cli.verbose {
-                            log_link(
-                                Some(cmd_name.bold()),
-                                &path.to_string_lossy(),
-                                &target_resolved.to_string_lossy(),
-                            );
+            _ => match outcome {
+                Outcome::Change(change) => {
+                    // relocate also rewrites links it can't fix, but says so
+                    if let (SlinkyCommand::Relocate { .. }, Change::Retarget { target }) =
+                        (&cli.command, &change)
+                        && Link::new(&link.origin, target).is_dangling
+                    {
+                        let new_target = target.to_string_lossy();
+                        log_warning(&cmd_name, "new target does not exist", &link.origin_str(), &new_target);
+                    }
+                    if !confirmed(confirm.as_mut(), || log_change(&cmd_name, &link, &change))? {
+                        summary.skipped += 1;
+                        continue;
+                    }
+                    summary.handle_operation(|| {
+                        if cli.verbose && (confirm.is_none() || printer.is_structured()) {
+                            describe_change(&mut printer, &cmd_name, &link, &change)?;
                         }
-                        if !cli.dry_run {
-                            fs::remove_file(path)?;
-                            create_hard_link(&target_resolved, path)?;
+                        plan.push(Action::new(&link, change)?);
+                        Ok(())
+                    });
+                }
+                Outcome::Skip(skip) => {
+                    log_skip(&cmd_name, &link, skip);
+                    if let (Skip::Ambiguous, Some(index)) = (skip, &index) {
+                        for candidate in index.candidates(&link) {
+                            eprintln!("  {}", candidate.display());
                         }
                     }
-                    Ok(())
-                });
+                    summary.skipped += 1;
+                }
+                Outcome::Unchanged => {}
+            },
+        }
+    }
+
+    printer.finish()?;
+    if let (Some(snapshot), SlinkyCommand::Snapshot { file, .. }) = (&mut snapshot, &cli.command) {
+        snapshot.save(file)?;
+    }
+
+    let done_label = if let Some(path) = &cli.save_plan {
+        plan.save(path)?;
+        summary.done += plan.actions.len();
+        "planned"
+    } else if cli.dry_run {
+        summary.done += plan.actions.len();
+        "planned"
+    } else {
+        let journal = open_journal(cli)?;
+        let mut finish = |applied: Result<()>| {
+            if summary.handle_operation(|| applied) {
+                summary.done += 1;
+            }
+        };
+        match cli.jobs {
+            // links in the same directory are changed one at a time, in order
+            Some(jobs) => {
+                let results = parallel::map_grouped(
+                    plan.actions.iter().collect(),
+                    jobs,
+                    |action| action.origin.parent().map(Path::to_path_buf),
+                    |action| apply_action(journal.as_ref(), action),
+                );
+                results.into_iter().for_each(finish);
+            }
+            None => {
+                for action in &plan.actions {
+                    finish(apply_action(journal.as_ref(), action));
+                }
             }
+        }
+        "changed"
+    };
 
-            SlinkyCommand::ToTree { hard } => {
-                handle_operation(|| {
-                    if is_dangling {
-                        log_dangling_link(&cmd_name, &path.to_string_lossy(), &target_str);
-                    } else if !target_resolved.is_dir() {
-                        log_link_err(
-                            Some(cmd_name.bold()),
-                            Some("skipping file".red()),
-                            &path.to_string_lossy(),
-                            &target_str,
-                        );
-                    } else {
-                        if cli.verbose {
-                            log_link(
-                                Some(cmd_name.bold()),
-                                &path.to_string_lossy(),
-                                &target_resolved.to_string_lossy(),
-                            );
-                        }
-                        if !cli.dry_run {
-                            fs::remove_file(path)?;
-                            if hard {
-                                create_hard_link_tree(&target_resolved, path)?;
-                            } else {
-                                create_symlink_tree(&target_resolved, path)?;
-                            }
-                        }
-                    }
-                    Ok(())
-                });
+    let done_label = match cli.command {
+        SlinkyCommand::List { .. } => "listed",
+        SlinkyCommand::Resolve => "resolved",
+        SlinkyCommand::Snapshot { .. } => "saved",
+        SlinkyCommand::Exec { .. } if !cli.dry_run => "ran",
+        _ => done_label,
+    };
+    if summary.failed > 0 || (!cli.command.is_report() && !summary.is_empty()) {
+        summary.log(&cmd_name, done_label);
+    }
+    Ok(summary)
+}
+
+/// Apply each action of a saved plan, refusing any whose link has changed since.
+fn apply_plan(cli: &SlinkyCli, path: &Path) -> Result<Summary> {
+    let plan = Plan::load(path)?;
+    let journal = open_journal(cli)?;
+    let mut printer = Printer::new(cli.format);
+    let mut summary = Summary::default();
+    let mut confirm = open_confirm(cli);
+    for action in &plan.actions {
+        if confirm.as_ref().is_some_and(Confirm::has_quit) {
+            break;
+        }
+        let mut declined = false;
+        let ok = summary.handle_operation(|| {
+            let link = action.check()?;
+            let cmd_name = action.change.to_string();
+            if !confirmed(confirm.as_mut(), || log_change(&cmd_name, &link, &action.change))? {
+                declined = true;
+                return Ok(());
             }
+            if cli.verbose && (confirm.is_none() || printer.is_structured()) {
+                describe_change(&mut printer, &action.change.to_string(), &link, &action.change)?;
+            }
+            if !cli.dry_run {
+                apply_action(journal.as_ref(), action)?;
+            }
+            Ok(())
+        });
+        if declined {
+            summary.skipped += 1;
+        } else if ok {
+            summary.done += 1;
+        }
+    }
+    printer.finish()?;
+    if !summary.is_empty() {
+        summary.log("apply", if cli.dry_run { "checked" } else { "changed" });
+    }
+    Ok(summary)
+}
 
-            SlinkyCommand::ReplaceWithTarget => {
-                handle_operation(|| {
-                    if is_dangling {
-                        log_dangling_link(&cmd_name, &path.to_string_lossy(), &target_str);
-                    } else {
-                        if cli.verbose {
-                            log_link(
-                                Some(cmd_name.bold()),
-                                &path.to_string_lossy(),
-                                &target_resolved.to_string_lossy(),
-                            );
-                        }
-                        if !cli.dry_run {
-                            let actual_target = fs::canonicalize(&target_resolved)?;
-                            fs::remove_file(path)?;
-                            fs::rename(actual_target, path)?;
-                        }
-                    }
-                    Ok(())
-                });
+/// Stow, unstow or restow packages. Every step is planned first, and none
+/// are taken if anything is in the way.
+fn stow(cli: &SlinkyCli, args: &StowArgs) -> Result<Summary> {
+    if cli.save_plan.is_some() {
+        anyhow::bail!("--save-plan cannot be used with {}", cli.command);
+    }
+    let cmd_name = cli.command.to_string();
+    let mut planner = Stow::new().excludes(Excludes::from_cli(cli)?);
+    for package in &args.packages {
+        let into = match &args.into {
+            Some(into) => into.clone(),
+            None => slinky::stow::default_target(package)?,
+        };
+        if !matches!(cli.command, SlinkyCommand::Stow(_)) {
+            planner.unstow(package, &into)?;
+        }
+        if !matches!(cli.command, SlinkyCommand::Unstow(_)) {
+            planner.stow(package, &into)?;
+        }
+    }
+
+    let mut summary = Summary {
+        skipped: planner.unchanged(),
+        ..Summary::default()
+    };
+    if !planner.conflicts().is_empty() {
+        for conflict in planner.conflicts() {
+            eprintln!(
+                "{}: {}: {}",
+                cmd_name.bold(),
+                conflict.reason.red(),
+                conflict.path.to_string_lossy().cyan()
+            );
+        }
+        summary.failed = planner.conflicts().len();
+        summary.log(&cmd_name, "changed");
+        return Ok(summary);
+    }
+
+    let ops = planner.ops();
+    let mut confirm = open_confirm(cli);
+    let describe = || ops.iter().for_each(|op| log_op(&cmd_name, op));
+    if !confirmed(confirm.as_mut(), describe)? {
+        summary.skipped += ops.len();
+    } else if cli.dry_run {
+        if cli.verbose {
+            describe();
+        }
+        summary.done = ops.len();
+    } else {
+        for op in ops {
+            if cli.verbose && confirm.is_none() {
+                log_op(&cmd_name, op);
+            }
+            // later steps depend on earlier ones
+            if !summary.handle_operation(|| op.apply()) {
+                break;
             }
+            summary.done += 1;
+        }
+    }
+    summary.log(&cmd_name, if cli.dry_run { "planned" } else { "changed" });
+    Ok(summary)
+}
+
+/// Make the links listed in a manifest as `slinky-ln` would, replacing any
+/// that are wrong, and with `prune` remove other symlinks under its roots.
+fn sync(cli: &SlinkyCli, manifest_path: &Path, prune: bool) -> Result<Summary> {
+    if cli.save_plan.is_some() {
+        anyhow::bail!("--save-plan cannot be used with {}", cli.command);
+    }
+    let manifest = Manifest::load(manifest_path)?;
+    let base = manifest_path.parent().unwrap_or(Path::new(""));
+    let journal = open_journal(cli)?;
+    let mut confirm = open_confirm(cli);
+    let mut summary = Summary::default();
+    let mut listed = Vec::new();
 
-            SlinkyCommand::Remove => {
+    for entry in &manifest.links {
+        if confirm.as_ref().is_some_and(Confirm::has_quit) {
+            break;
+        }
+        let (mut changed, mut declined) = (false, false);
+        summary.handle_operation(|| {
+            let ln = entry.to_cli()?;
+            let spec = LinkSpec::exact(&ln, base)?;
+            listed.push(tidy_path(&path::absolute(&spec.origin)?));
+            let origin = spec.origin.to_string_lossy();
+            let new = match spec.kind {
+                Kind::Symlink => spec.target.to_string_lossy().to_string(),
+                kind => format!("{} ({})", ln.target.as_deref().unwrap_or_default(), kind),
+            };
+            if spec.exists() {
                 if cli.verbose {
-                    log_link(
-                        Some(cmd_name.bold().red()),
-                        &path.to_string_lossy(),
-                        &target_str,
-                    );
+                    log_diff(' ', &origin, None, &new);
                 }
-                if !cli.dry_run {
-                    handle_operation(|| {
-                        fs::remove_file(path)?;
-                        Ok(())
-                    });
+                return Ok(());
+            }
+
+            let existing = fs::symlink_metadata(&spec.origin).ok();
+            let old = match &existing {
+                Some(meta) if meta.is_symlink() => Some(fs::read_link(&spec.origin)?),
+                Some(meta) if meta.is_dir() => anyhow::bail!("{}: existing directory is in the way", origin),
+                Some(_) if !ln.force => {
+                    anyhow::bail!("{}: existing file is in the way, set force to replace it", origin)
                 }
+                _ => None,
+            };
+            let old = old.as_ref().map(|old| old.to_string_lossy());
+            let sign = if existing.is_some() { '~' } else { '+' };
+            let describe = || log_diff(sign, &origin, old.as_deref(), &new);
+            if confirm.is_none() {
+                describe();
             }
+            if !confirmed(confirm.as_mut(), describe)? {
+                declined = true;
+                return Ok(());
+            }
+            if !cli.dry_run {
+                // a replaced symlink is journaled so that undo can put it back
+                if existing.as_ref().is_some_and(|meta| meta.is_symlink()) {
+                    apply_change(journal.as_ref(), &spec.origin, spec.change())?;
+                } else {
+                    spec.create(existing.is_some())?;
+                }
+            }
+            changed = true;
+            Ok(())
+        });
+        if declined {
+            summary.skipped += 1;
+        } else if changed {
+            summary.done += 1;
+        }
+    }
 
-            SlinkyCommand::Exec { ref cmd_string } => {
-                handle_operation(|| {
-                    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
-                    if cli.verbose {
-                        println!(
-                            "{}: {} {} {}",
-                            cmd_name.bold(),
-                            cmd_string.blue(),
-                            path.to_string_lossy().cyan(),
-                            target_str.yellow(),
-                        );
+    if prune && summary.failed > 0 {
+        eprintln!("{}: not pruning, since some links could not be synced", "Warning".yellow());
+    } else if prune {
+        let excludes = Excludes::from_cli(cli)?;
+        for root in manifest.roots() {
+            let root = base.join(root);
+            if !root.is_dir() {
+                continue;
+            }
+            let mut walker = WalkDir::new(&root).min_depth(1).into_iter();
+            while let Some(entry) = walker.next() {
+                if confirm.as_ref().is_some_and(Confirm::has_quit) {
+                    break;
+                }
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        summary.handle_operation(|| Err(e.into()));
+                        continue;
+                    }
+                };
+                let path = entry.path();
+                let is_dir = entry.file_type().is_dir();
+                let abs_path = match path::absolute(path) {
+                    Ok(abs_path) => tidy_path(&abs_path),
+                    Err(e) => {
+                        summary.handle_operation(|| Err(e.into()));
+                        continue;
+                    }
+                };
+                // links inside a listed tree are the tree's own
+                if excludes.is_excluded(&root, path, is_dir) || listed.iter().any(|origin| abs_path.starts_with(origin)) {
+                    if is_dir {
+                        walker.skip_current_dir();
+                    }
+                    continue;
+                }
+                if !entry.path_is_symlink() {
+                    continue;
+                }
+                let mut declined = false;
+                let ok = summary.handle_operation(|| {
+                    let target = fs::read_link(path)?;
+                    let describe = || log_diff('-', &path.to_string_lossy(), None, &target.to_string_lossy());
+                    if confirm.is_none() {
+                        describe();
+                    }
+                    if !confirmed(confirm.as_mut(), describe)? {
+                        declined = true;
+                        return Ok(());
                     }
                     if !cli.dry_run {
-                        Command::new(shell)
-                            .arg("-c")
-                            .arg(cmd_string)
-                            .arg("--")
-                            .arg(path)
-                            .arg(&target_path)
-                            .status()?;
+                        apply_change(journal.as_ref(), path, Change::Remove)?;
                     }
                     Ok(())
                 });
+                if declined {
+                    summary.skipped += 1;
+                } else if ok {
+                    summary.done += 1;
+                }
+            }
+        }
+    }
+
+    summary.log(&cli.command.to_string(), if cli.dry_run { "planned" } else { "changed" });
+    Ok(summary)
+}
+
+/// Make the links recorded in a snapshot again under `into`.
+fn restore(cli: &SlinkyCli, file: &Path, into: &Path, force: bool) -> Result<Summary> {
+    if cli.save_plan.is_some() {
+        anyhow::bail!("--save-plan cannot be used with {}", cli.command);
+    }
+    let snapshot = Snapshot::load(file)?;
+    let cmd_name = cli.command.to_string();
+    let journal = open_journal(cli)?;
+    let mut confirm = open_confirm(cli);
+    let mut summary = Summary::default();
+    for entry in &snapshot.links {
+        if confirm.as_ref().is_some_and(Confirm::has_quit) {
+            break;
+        }
+        let origin = into.join(&entry.origin);
+        let (mut changed, mut declined) = (false, false);
+        summary.handle_operation(|| {
+            let existing = fs::symlink_metadata(&origin).ok();
+            match &existing {
+                Some(meta) if meta.is_symlink() && fs::read_link(&origin)? == entry.target => return Ok(()),
+                Some(meta) if meta.is_dir() => {
+                    anyhow::bail!("{}: existing directory is in the way", origin.display())
+                }
+                Some(_) if !force => {
+                    anyhow::bail!("{}: existing file is in the way, use --force to replace it", origin.display())
+                }
+                _ => {}
+            }
+            let describe = || log_link(Some(cmd_name.bold()), &origin.to_string_lossy(), &entry.target.to_string_lossy());
+            if cli.verbose && confirm.is_none() {
+                describe();
+            }
+            if !confirmed(confirm.as_mut(), describe)? {
+                declined = true;
+                return Ok(());
+            }
+            if !cli.dry_run {
+                if let Some(dir) = origin.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                match &existing {
+                    Some(meta) if meta.is_symlink() => {
+                        let change = Change::Retarget {
+                            target: entry.target.clone(),
+                        };
+                        apply_change(journal.as_ref(), &origin, change)?;
+                    }
+                    Some(_) => replace_symlink(&entry.target, &origin)?,
+                    None => symlink(&entry.target, &origin)?,
+                }
+                // the link is made either way, so an owner that can't be set
+                // (usually for want of root) is only worth a warning
+                if let Some(owner) = entry.owner
+                    && let Err(err) = lchown(&origin, Some(owner.uid), Some(owner.gid))
+                {
+                    eprintln!("{}: {}: cannot set owner: {}", "Warning".yellow(), origin.display(), err);
+                }
+            }
+            changed = true;
+            Ok(())
+        });
+        if declined {
+            summary.skipped += 1;
+        } else if changed {
+            summary.done += 1;
+        }
+    }
+    summary.log(&cmd_name, if cli.dry_run { "planned" } else { "restored" });
+    Ok(summary)
+}
+
+/// Report how the links under two directories, or in two snapshots, differ.
+fn diff(cli: &SlinkyCli, old: &Path, new: &Path, exit_code: bool) -> Result<Summary> {
+    let load = |path: &Path| -> Result<Snapshot> {
+        if !path.is_dir() {
+            return Snapshot::load(path);
+        }
+        let mut snapshot = Snapshot::new(path)?;
+        for link in LinkScanner::new(path).with_cli_options(cli)?.scan() {
+            snapshot.push(&link?, false)?;
+        }
+        Ok(snapshot)
+    };
+    let differences = load(old)?.diff(&load(new)?);
+
+    let (mut added, mut removed, mut retargeted, mut status_changed) = (0, 0, 0, 0);
+    for difference in &differences {
+        let origin = difference.origin.to_string_lossy();
+        match (&difference.old, &difference.new) {
+            (None, Some(new)) => {
+                log_diff('+', &origin, None, &new.target.to_string_lossy());
+                added += 1;
+            }
+            (Some(old), None) => {
+                log_diff('-', &origin, None, &old.target.to_string_lossy());
+                removed += 1;
             }
+            (Some(old), Some(new)) => {
+                let mut target = new.target.to_string_lossy().to_string();
+                if old.status != new.status {
+                    target = format!("{} ({} => {})", target, old.status, new.status);
+                }
+                if old.target != new.target {
+                    log_diff('~', &origin, Some(&old.target.to_string_lossy()), &target);
+                    retargeted += 1;
+                } else {
+                    log_diff('!', &origin, None, &target);
+                    status_changed += 1;
+                }
+            }
+            (None, None) => unreachable!("a difference is in at least one snapshot"),
         }
     }
+    eprintln!(
+        "{}: {} added, {} removed, {} retargeted, {} changed status",
+        cli.command.to_string().bold(),
+        added,
+        removed,
+        retargeted,
+        status_changed
+    );
+
+    // with --exit-code, differences fail the run like they would for diff(1)
+    Ok(Summary {
+        done: differences.len(),
+        failed: if exit_code { differences.len() } else { 0 },
+        ..Summary::default()
+    })
+}
+
+/// Set up --interactive, warning up front if there is no terminal to ask on.
+fn open_confirm(cli: &SlinkyCli) -> Option<Confirm> {
+    let confirm = cli.interactive.then(Confirm::new)?;
+    if !confirm.can_ask() {
+        eprintln!("{}: stdin is not a terminal, refusing every change", "Warning".yellow());
+    }
+    Some(confirm)
+}
+
+/// Under --interactive, show a change with `describe` and ask whether to make it.
+fn confirmed(confirm: Option<&mut Confirm>, describe: impl FnOnce()) -> Result<bool> {
+    match confirm {
+        Some(confirm) => Ok(confirm.ask(describe)?),
+        None => Ok(true),
+    }
+}
+
+/// Print a planned change for --verbose, in whichever format was asked for.
+fn describe_change(printer: &mut Printer, cmd_name: &str, link: &Link, change: &Change) -> Result<()> {
+    if printer.is_structured() {
+        printer.emit(Record::for_change(link, change))?;
+    } else {
+        log_change(cmd_name, link, change);
+    }
     Ok(())
 }
+
+fn open_journal(cli: &SlinkyCli) -> Result<Option<Journal>> {
+    (!cli.no_journal).then(Journal::new).transpose()
+}
+
+/// Make `change` to the symlink at `origin`, journaled so that undo can
+/// reverse it.
+fn apply_change(journal: Option<&Journal>, origin: &Path, change: Change) -> Result<()> {
+    apply_action(journal, &Action::new(&Link::read(origin)?, change)?)
+}
+
+/// Make `action`, journaling it first unless --no-journal was given.
+fn apply_action(journal: Option<&Journal>, action: &Action) -> Result<()> {
+    match journal {
+        Some(journal) => journal.apply(action),
+        None => action.apply(),
+    }
+}
+
+/// Reverse the actions recorded in one or more journals, newest first.
+fn undo(cli: &SlinkyCli, last: usize, journal_path: Option<&Path>) -> Result<Summary> {
+    let journals = match journal_path {
+        Some(path) => vec![path.to_path_buf()],
+        None => {
+            let journals = journal::list_journals()?;
+            if journals.is_empty() {
+                anyhow::bail!("nothing to undo");
+            }
+            journals.into_iter().rev().take(last).collect()
+        }
+    };
+    let mut printer = Printer::new(cli.format);
+    let mut summary = Summary::default();
+    let mut confirm = open_confirm(cli);
+    for path in journals {
+        let entries = journal::load(&path)?;
+        let mut remaining = Vec::new();
+        for entry in entries.into_iter().rev() {
+            let action = &entry.action;
+            // entries not undone stay in the journal, including any after a quit
+            if confirm.as_ref().is_some_and(Confirm::has_quit) {
+                remaining.push(entry);
+                continue;
+            }
+            let describe = || {
+                log_link(
+                    Some("undo".bold()),
+                    &action.origin.to_string_lossy(),
+                    &action.old_target.to_string_lossy(),
+                )
+            };
+            if !confirmed(confirm.as_mut(), describe)? {
+                summary.skipped += 1;
+                remaining.push(entry);
+                continue;
+            }
+            if cli.verbose && (confirm.is_none() || printer.is_structured()) {
+                if printer.is_structured() {
+                    printer.emit(Record::new(&Link::new(&action.origin, &action.old_target), "undo"))?;
+                } else {
+                    log_link(
+                        Some("undo".bold()),
+                        &action.origin.to_string_lossy(),
+                        &action.old_target.to_string_lossy(),
+                    );
+                }
+            }
+            if cli.dry_run || summary.handle_operation(|| journal::undo(action)) {
+                summary.done += 1;
+            } else {
+                remaining.push(entry);
+            }
+        }
+        if !cli.dry_run {
+            remaining.reverse();
+            journal::retire(&path, &remaining)?;
+        }
+    }
+    printer.finish()?;
+    summary.log("undo", if cli.dry_run { "planned" } else { "restored" });
+    Ok(summary)
+}
+
+/// Ask on stderr which of `candidates` a link should point at, reading the
+/// answer from stdin. An empty answer, or end of input, picks none.
+fn choose_candidate(cmd_name: &str, link: &Link, candidates: &[PathBuf]) -> Result<Option<PathBuf>> {
+    log_link_err(Some(cmd_name.bold()), None, &link.origin_str(), &link.target_str());
+    for (i, candidate) in candidates.iter().enumerate() {
+        eprintln!("  {}) {}", i + 1, candidate.display());
+    }
+    let mut stdin = io::stdin().lock();
+    loop {
+        eprint!("choose 1-{}, or nothing to skip: ", candidates.len());
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        if stdin.read_line(&mut answer)? == 0 {
+            eprintln!();
+            return Ok(None);
+        }
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        if let Ok(n) = answer.parse::<usize>()
+            && let Some(candidate) = n.checked_sub(1).and_then(|i| candidates.get(i))
+        {
+            return Ok(Some(candidate.clone()));
+        }
+    }
+}
+
+/// Work out what a transforming subcommand would do to `link`.
+fn plan_link(cli: &SlinkyCli, edit_re: Option<&Regex>, index: Option<&Index>, link: &Link) -> Result<Outcome> {
+    match &cli.command {
+        SlinkyCommand::ToRelative {
+            no_canonicalize,
+            within,
+        } => transform::to_relative(link, !no_canonicalize, within.as_deref()),
+        SlinkyCommand::ToAbsolute { no_canonicalize } => transform::to_absolute(link, !no_canonicalize),
+        SlinkyCommand::Tidy => transform::tidy(link),
+        SlinkyCommand::EditTarget {
+            replace,
+            replace_all,
+            ..
+        } => {
+            let re = edit_re.expect("edit-target pattern is compiled up front");
+            transform::edit_target(link, re, replace, *replace_all)
+        }
+        SlinkyCommand::ToHardlink => transform::to_hardlink(link),
+        SlinkyCommand::ToTree { hard } => transform::to_tree(link, *hard),
+        SlinkyCommand::Flatten { hops } => transform::flatten(link, cli.max_hops, *hops),
+        SlinkyCommand::Relocate {
+            from,
+            to,
+            existing_only,
+        } => transform::relocate(link, from, to, *existing_only),
+        SlinkyCommand::Repair { .. } => {
+            let index = index.expect("repair search directories are indexed up front");
+            transform::repair(link, index)
+        }
+        SlinkyCommand::ReplaceWithTarget => transform::replace_with_target(link),
+        SlinkyCommand::Remove => transform::remove(link),
+        SlinkyCommand::List { .. }
+        | SlinkyCommand::Resolve
+        | SlinkyCommand::Exec { .. }
+        | SlinkyCommand::Apply { .. }
+        | SlinkyCommand::Undo { .. }
+        | SlinkyCommand::Stow(_)
+        | SlinkyCommand::Unstow(_)
+        | SlinkyCommand::Restow(_)
+        | SlinkyCommand::Sync { .. }
+        | SlinkyCommand::Snapshot { .. }
+        | SlinkyCommand::Restore { .. }
+        | SlinkyCommand::Diff { .. } => Ok(Outcome::Unchanged),
+    }
+}
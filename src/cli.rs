@@ -1,52 +1,128 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser)]
-#[command(name = "slinky", version = "0.1.0", about = "Wrangle symbolic links")]
+#[command(
+    name = "slinky",
+    version = "0.1.0",
+    about = "Wrangle symbolic links",
+    after_help = "Exit status is 0 if every link was handled, 1 if some links failed, and 2 on a fatal error.",
+    subcommand_precedence_over_arg = true
+)]
 pub struct SlinkyCli {
-    /// The path in which to search for symlinks
-    #[arg(default_value = ".")]
-    pub path: PathBuf,
+    /// The paths in which to search for symlinks. Links reachable from several are only acted on once.
+    #[arg(default_value = ".", value_name = "PATH")]
+    pub paths: Vec<PathBuf>,
+
+    /// Act on the NUL-separated list of symlink paths read from stdin instead of searching any PATH.
+    #[arg(global = true, long, conflicts_with_all = ["max_depth", "follow", "one_file_system", "exclude", "include", "exclude_from", "gitignore"])]
+    pub from_stdin0: bool,
 
     /// What to do to each symlink found.
     #[command(subcommand)]
     pub command: SlinkyCommand,
 
-    /// Only act on dangling symlinks.
-    #[arg(short = 'x', long)]
+    /// Only act on dangling symlinks: those whose target is missing or part of a loop.
+    #[arg(global = true, short = 'x', long)]
     pub only_dangling: bool,
 
-    /// Only act on 'attached' (non-dangling) symlinks.
-    #[arg(short = 'a', long)]
+    /// Only act on 'attached' symlinks, whose target can be reached.
+    #[arg(global = true, short = 'a', long)]
     pub only_attached: bool,
 
+    /// Only act on symlinks that are part of, or lead into, a loop.
+    #[arg(global = true, long)]
+    pub only_looping: bool,
+
+    /// Report chains of more than NUM symlinks as too long.
+    #[arg(global = true, long, value_name = "NUM", default_value_t = 40)]
+    pub max_hops: usize,
+
     /// Only act on absolute symlinks.
-    #[arg(short = 'b', long)]
+    #[arg(global = true, short = 'b', long)]
     pub only_absolute: bool,
 
     /// Only act on relative symlinks.
-    #[arg(short = 'r', long)]
+    #[arg(global = true, short = 'r', long)]
     pub only_relative: bool,
 
     /// Only act on symlinks whose origin path matches the given regex
-    #[arg(short = 'o', long, value_name = "FILTER")]
+    #[arg(global = true, short = 'o', long, value_name = "FILTER")]
     pub filter_origin: Option<String>,
 
     /// Only act on symlinks whose target string matches the given regex.
-    #[arg(short = 't', long, value_name = "FILTER")]
+    #[arg(global = true, short = 't', long, value_name = "FILTER")]
     pub filter_target: Option<String>,
 
     /// Descend at most NUM directories
-    #[arg(short = 'd', long, value_name = "NUM")]
+    #[arg(global = true, short = 'd', long, value_name = "NUM")]
     pub max_depth: Option<usize>,
 
+    /// Descend into symlinked directories, reporting links at the path they were reached by. Loops are not followed.
+    #[arg(global = true, short = 'L', long)]
+    pub follow: bool,
+
+    /// Don't descend into directories on other filesystems than the search path's.
+    #[arg(global = true, short = 'X', long)]
+    pub one_file_system: bool,
+
+    /// Skip paths matching GLOB (gitignore syntax, relative to the search path). Excluded directories aren't descended into.
+    #[arg(global = true, long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Don't skip paths matching GLOB, even if excluded. Nothing inside an excluded directory is seen, though.
+    #[arg(global = true, long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Skip paths matching the patterns in FILE, one per line in gitignore syntax.
+    #[arg(global = true, long, value_name = "FILE")]
+    pub exclude_from: Vec<PathBuf>,
+
+    /// Also skip paths ignored by .gitignore and .ignore files under the search path.
+    #[arg(global = true, long)]
+    pub gitignore: bool,
+
+    /// Walk the tree and process links on N threads (0 for one per CPU). Links are then handled in order of origin path.
+    #[arg(global = true, short = 'j', long, value_name = "N")]
+    pub jobs: Option<usize>,
+
     /// Describe any changes to be made.
-    #[arg(short, long)]
+    #[arg(global = true, short, long)]
     pub verbose: bool,
 
     /// Don't make any changes.
-    #[arg(short = 'n', long)]
+    #[arg(global = true, short = 'n', long)]
     pub dry_run: bool,
+
+    /// Show each change and ask before making it. Everything is refused if stdin isn't a terminal.
+    #[arg(global = true, short = 'i', long, conflicts_with = "dry_run")]
+    pub interactive: bool,
+
+    /// How to print links found by `list` and changes described by --verbose.
+    #[arg(global = true, long, value_enum, default_value_t = OutputFormat::Text, value_name = "FORMAT")]
+    pub format: OutputFormat,
+
+    /// Write the planned changes to FILE as JSON instead of making them ('-' for stdout).
+    #[arg(global = true, long, value_name = "FILE")]
+    pub save_plan: Option<PathBuf>,
+
+    /// Don't record changes in the undo journal.
+    #[arg(global = true, long)]
+    pub no_journal: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Colored `origin -> target` lines.
+    Text,
+    /// A single JSON array of records.
+    Json,
+    /// One JSON record per line.
+    Ndjson,
+    /// Tab-separated fields, one record per line, with tabs, newlines and backslashes escaped.
+    Tsv,
+    /// NUL-terminated fields, in the same order as tsv, with no escaping.
+    Null,
 }
 
 #[derive(Subcommand, Debug, strum::Display, Clone)]
@@ -56,18 +132,36 @@ pub enum SlinkyCommand {
     /// List symlinks.
     #[command(visible_alias = "ls")]
     List {
-        /// Prefix the link description with its attached/dangling status.
+        /// Prefix the link description with its attached/dangling status. Text output only.
         #[arg(short, long)]
         status: bool,
 
-        /// Print only the origin path.
+        /// Print only the origin path. Text output only.
         #[arg(long)]
         origin_only: bool,
+
+        /// Print only the origin path, terminated by NUL instead of newline. Implies --origin-only.
+        #[arg(short = '0', long)]
+        null: bool,
     },
+    /// Show every hop from each symlink to what it finally points at, and where the chain breaks.
+    Resolve,
     /// Convert absolute symlinks to relative symlinks. Fails on dangling symlinks.
-    ToRelative,
+    ToRelative {
+        /// Work out the new target lexically, without resolving symlinks along the way.
+        #[arg(long)]
+        no_canonicalize: bool,
+
+        /// Only make links relative if their target is inside DIR; make those pointing outside it absolute.
+        #[arg(long, value_name = "DIR")]
+        within: Option<PathBuf>,
+    },
     /// Convert relative symlinks to absolute symlinks. Fails on dangling symlinks.
-    ToAbsolute,
+    ToAbsolute {
+        /// Work out the new target lexically, without resolving symlinks along the way.
+        #[arg(long)]
+        no_canonicalize: bool,
+    },
     /// Lexically tidy the target path (e.g., remove redundant `..` or `.`)
     Tidy,
     /// Edit the target string of symlinks by replacing regex matches.
@@ -86,6 +180,132 @@ pub enum SlinkyCommand {
         #[arg(short = 'H', long)]
         hard: bool,
     },
+    /// Point symlinks that lead to other symlinks straight at the final target, keeping them relative or absolute.
+    Flatten {
+        /// Skip over at most N intermediate links.
+        #[arg(long, value_name = "N")]
+        hops: Option<usize>,
+    },
+    /// Point links into a directory tree that has moved at the same place in its new location.
+    #[command(long_about = concat!(
+        "Point links into a directory tree that has moved at the same place in its new location. ",
+        "Targets under OLD, compared a whole path component at a time, are rewritten to be under NEW, ",
+        "keeping relative targets relative. Links that would still dangle are reported."
+    ))]
+    Relocate {
+        /// Where the tree used to be.
+        #[arg(long, value_name = "OLD")]
+        from: PathBuf,
+
+        /// Where the tree is now.
+        #[arg(long, value_name = "NEW")]
+        to: PathBuf,
+
+        /// Leave links alone if their new target wouldn't exist either.
+        #[arg(long)]
+        existing_only: bool,
+    },
+    /// Point dangling symlinks at a file of the same name found under the search directories.
+    #[command(long_about = concat!(
+        "Point dangling symlinks at a file of the same name found under the search directories. ",
+        "Candidates sharing more trailing path components with the old target rank higher, ",
+        "and a link is fixed when one candidate ranks above the rest, keeping relative targets relative. ",
+        "Links with several equally good candidates are reported, or with --choose, offered as a choice."
+    ))]
+    Repair {
+        /// Look for missing targets under DIR. May be repeated.
+        #[arg(long = "search", value_name = "DIR", required = true)]
+        search: Vec<PathBuf>,
+
+        /// Ask which candidate to use when several are equally good.
+        #[arg(long)]
+        choose: bool,
+    },
+    /// Link the contents of package directories into a target directory, like GNU Stow.
+    #[command(long_about = concat!(
+        "Link the contents of package directories into a target directory, like GNU Stow. ",
+        "Links are relative, and a directory missing from the target is linked whole. ",
+        "When a second package needs a directory already linked from another, the link is split ",
+        "into a directory of links. If anything else is in the way, nothing is changed. ",
+        "Changes aren't journaled: use unstow to reverse them."
+    ))]
+    Stow(StowArgs),
+    /// Remove the links into package directories made by stow, folding split directories back together.
+    #[command(long_about = concat!(
+        "Remove the links into package directories made by stow, folding split directories back together. ",
+        "Changes aren't journaled: use stow to reverse them."
+    ))]
+    Unstow(StowArgs),
+    /// Unstow and stow packages again, dropping links to anything removed from them.
+    Restow(StowArgs),
+    /// Create and fix links to match a manifest, printing what changes.
+    #[command(long_about = concat!(
+        "Create and fix links to match a manifest, printing what changes. ",
+        "The manifest is a TOML file of [[link]] tables, each with an origin and a target, and any of the ",
+        "slinky-ln options relative, absolute, hard, tree, allow-dangling, force and dereference. ",
+        "Relative paths are taken from the manifest's directory. ",
+        "Missing links are made (+), links that point elsewhere are replaced (~), and with --prune, ",
+        "other symlinks under the directories listed as roots are removed (-). ",
+        "Replaced and pruned symlinks are journaled so undo can put them back; new links and replaced files aren't."
+    ))]
+    Sync {
+        /// The manifest file.
+        manifest: PathBuf,
+
+        /// Remove symlinks under the manifest's roots that it doesn't list.
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Save the symlinks found to a file, to be made again later by restore.
+    #[command(long_about = concat!(
+        "Save the symlinks found to a file, to be made again later by restore. ",
+        "Each link is recorded as JSON with its path relative to the search root, its target exactly as stored, ",
+        "and whether it dangled. Only one search root may be given."
+    ))]
+    Snapshot {
+        /// The file to write ('-' for stdout).
+        file: PathBuf,
+
+        /// Also record each link's owner and mode.
+        #[arg(long)]
+        metadata: bool,
+    },
+    /// Make the symlinks saved by snapshot again, under another directory or after they were lost.
+    #[command(long_about = concat!(
+        "Make the symlinks saved by snapshot again, under another directory or after they were lost. ",
+        "Missing parent directories are created, and recorded owners are restored where permitted. ",
+        "Links already in place are left alone. ",
+        "Replaced symlinks are journaled so undo can put them back; new links and replaced files aren't."
+    ))]
+    Restore {
+        /// The snapshot file ('-' for stdin).
+        file: PathBuf,
+
+        /// Where to make the links.
+        #[arg(long, value_name = "DIR", default_value = ".")]
+        into: PathBuf,
+
+        /// Replace files and other symlinks in the way. Directories are never replaced.
+        #[arg(short = 'f', long)]
+        force: bool,
+    },
+    /// Compare the symlinks under two directories, or saved by snapshot, matching them by relative path.
+    #[command(long_about = concat!(
+        "Compare the symlinks under two directories, or saved by snapshot, matching them by path relative to each. ",
+        "Links are reported as added (+), removed (-), pointed elsewhere (~), ",
+        "or as having started or stopped dangling (!). Directories are searched as set by the search options."
+    ))]
+    Diff {
+        /// The directory or snapshot to compare from ('-' for a snapshot on stdin).
+        old: PathBuf,
+
+        /// The directory or snapshot to compare to.
+        new: PathBuf,
+
+        /// Exit with status 1 if there are any differences.
+        #[arg(long)]
+        exit_code: bool,
+    },
     /// Move the target to the symlink's location. Fails on dangling symlinks.
     ReplaceWithTarget,
     /// Remove symlinks.
@@ -98,13 +318,55 @@ pub enum SlinkyCommand {
         "It will be run using $SHELL, with $1 bound to the link origin and $2 bound to the link target."
     ))]
     Exec { cmd_string: String },
+    /// Apply a plan written by --save-plan. Fails on links that changed since the plan was made.
+    Apply {
+        /// The plan file ('-' for stdin).
+        plan: PathBuf,
+    },
+    /// Undo the changes recorded by previous runs, most recent first.
+    #[command(long_about = concat!(
+        "Undo the changes recorded by previous runs, most recent first. ",
+        "Journals are kept in $SLINKY_STATE_DIR, or $XDG_STATE_HOME/slinky, or ~/.local/state/slinky. ",
+        "Links that have changed since they were recorded are left alone."
+    ))]
+    Undo {
+        /// Undo the last N runs.
+        #[arg(long, value_name = "N", default_value_t = 1, conflicts_with = "journal")]
+        last: usize,
+
+        /// Undo the run recorded in the given journal file.
+        #[arg(long, value_name = "FILE")]
+        journal: Option<PathBuf>,
+    },
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct StowArgs {
+    /// The package directories.
+    #[arg(required = true, value_name = "PACKAGE_DIR")]
+    pub packages: Vec<PathBuf>,
+
+    /// Where to link them into. Defaults to the parent of the directory holding each package.
+    #[arg(long, value_name = "TARGET_DIR")]
+    pub into: Option<PathBuf>,
+}
+
+impl SlinkyCommand {
+    /// Whether the command only reports on links, without changing anything.
+    pub fn is_report(&self) -> bool {
+        matches!(
+            self,
+            SlinkyCommand::List { .. } | SlinkyCommand::Resolve | SlinkyCommand::Snapshot { .. }
+        )
+    }
 }
 
 #[derive(Parser)]
 #[command(name = "slinky-ln", version = "0.1.0", about = "Create symbolic links without confusion")]
 pub struct SlinkyLnCli {
     /// The path that the link will point to.
-    pub target: String,
+    #[arg(required_unless_present = "batch")]
+    pub target: Option<String>,
 
     /// The path where the link will live. If a directory is provided, the link will be created inside that directory with the same basename as the target.
     #[arg(default_value = ".")]
@@ -145,4 +407,12 @@ pub struct SlinkyLnCli {
     /// Don't modify the filesystem.
     #[arg(short = 'n', long)]
     pub dry_run: bool,
+
+    /// Make every link listed in FILE ('-' for stdin), one `target<TAB>origin` per line. A third tab-separated field may add flags for that line alone, such as `-f` or `--hard`.
+    #[arg(long, value_name = "FILE", conflicts_with = "target")]
+    pub batch: Option<PathBuf>,
+
+    /// Read the --batch input as NUL-separated target and origin pairs instead of lines.
+    #[arg(short = '0', long, requires = "batch")]
+    pub null: bool,
 }
@@ -0,0 +1,98 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// A reply to "apply this change?".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Answer {
+    Yes,
+    No,
+    /// This change and every one after it.
+    All,
+    /// Neither this change nor any after it.
+    Quit,
+}
+
+/// Read answers from `input` until one makes sense, prompting on `output`.
+/// End of input means quit.
+pub fn ask(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<Answer> {
+    loop {
+        write!(output, "apply? [y]es, [n]o, [a]ll, [q]uit: ")?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(Answer::Quit);
+        }
+        match line.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Ok(Answer::Yes),
+            "n" | "no" => return Ok(Answer::No),
+            "a" | "all" => return Ok(Answer::All),
+            "q" | "quit" => return Ok(Answer::Quit),
+            _ => {}
+        }
+    }
+}
+
+/// Asks on the terminal before each change for --interactive, remembering
+/// "all" and "quit". Without a terminal to ask on, every change is refused.
+#[derive(Debug)]
+pub struct Confirm {
+    terminal: bool,
+    all: bool,
+    quit: bool,
+}
+
+impl Confirm {
+    pub fn new() -> Self {
+        Self {
+            terminal: io::stdin().is_terminal(),
+            all: false,
+            quit: false,
+        }
+    }
+
+    /// Whether there is a terminal to ask on.
+    pub fn can_ask(&self) -> bool {
+        self.terminal
+    }
+
+    /// Whether the user has quit, so nothing more should be done.
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// Show a change with `describe` and ask whether to go ahead with it.
+    pub fn ask(&mut self, describe: impl FnOnce()) -> io::Result<bool> {
+        if self.quit || !self.terminal {
+            return Ok(false);
+        }
+        describe();
+        if self.all {
+            return Ok(true);
+        }
+        let answer = ask(&mut io::stdin().lock(), &mut io::stderr())?;
+        self.all = answer == Answer::All;
+        self.quit = answer == Answer::Quit;
+        Ok(matches!(answer, Answer::Yes | Answer::All))
+    }
+}
+
+impl Default for Confirm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ask() {
+        let mut output = Vec::new();
+        let answer = ask(&mut "maybe\n\nA\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(answer, Answer::All);
+        assert_eq!(String::from_utf8(output).unwrap().matches("apply?").count(), 3);
+        assert_eq!(ask(&mut "no\n".as_bytes(), &mut Vec::new()).unwrap(), Answer::No);
+        assert_eq!(ask(&mut "".as_bytes(), &mut Vec::new()).unwrap(), Answer::Quit);
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::{Context, Result};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::cli::SlinkyCli;
+
+/// Names of the per-directory ignore files honored by [`Excludes::ignore_files`],
+/// later ones taking precedence.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// Decides which paths a walk skips: gitignore-style patterns given up front
+/// and, optionally, the `.gitignore` and `.ignore` files found along the way.
+/// Patterns are matched against paths relative to the search root.
+#[derive(Debug, Clone, Default)]
+pub struct Excludes {
+    globs: Option<Gitignore>,
+    ignore_files: bool,
+    /// Ignore files already read, by directory.
+    loaded: Arc<Mutex<HashMap<PathBuf, Arc<Gitignore>>>>,
+}
+
+impl Excludes {
+    /// Compile `patterns` as the lines of a gitignore file: later lines win
+    /// over earlier ones, and a leading `!` re-includes.
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self> {
+        if patterns.is_empty() {
+            return Ok(Self::default());
+        }
+        let mut builder = GitignoreBuilder::new("");
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            builder
+                .add_line(None, pattern)
+                .with_context(|| format!("{}: invalid pattern", pattern))?;
+        }
+        Ok(Self {
+            globs: Some(builder.build()?),
+            ..Self::default()
+        })
+    }
+
+    /// Gather --exclude-from, --exclude and --include patterns, in that order of
+    /// precedence from lowest to highest.
+    pub fn from_cli(cli: &SlinkyCli) -> Result<Self> {
+        let mut patterns = Vec::new();
+        for file in &cli.exclude_from {
+            let text = fs::read_to_string(file)
+                .with_context(|| format!("{}: cannot read exclude file", file.display()))?;
+            patterns.extend(text.lines().map(str::to_string));
+        }
+        patterns.extend(cli.exclude.iter().cloned());
+        patterns.extend(cli.include.iter().map(|glob| format!("!{}", glob)));
+        Ok(Self::new(&patterns)?.ignore_files(cli.gitignore))
+    }
+
+    /// Also skip whatever `.gitignore` and `.ignore` files in the walked
+    /// directories ignore. Patterns given up front take precedence.
+    pub fn ignore_files(mut self, yes: bool) -> Self {
+        self.ignore_files = yes;
+        self
+    }
+
+    /// Whether `path`, found by walking `root`, should be skipped (and, if it
+    /// is a directory, not descended into). The root itself never is.
+    pub fn is_excluded(&self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        let Ok(rel) = path.strip_prefix(root) else {
+            return false;
+        };
+        if rel.as_os_str().is_empty() {
+            return false;
+        }
+        if let Some(globs) = &self.globs {
+            match globs.matched(rel, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        if self.ignore_files {
+            // the nearest ignore file with an opinion decides
+            for dir in path.ancestors().skip(1) {
+                let Ok(rel) = path.strip_prefix(dir) else {
+                    break;
+                };
+                match self.ignore_files_in(dir).matched(rel, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+                if dir == root {
+                    break;
+                }
+            }
+        }
+        false
+    }
+
+    fn ignore_files_in(&self, dir: &Path) -> Arc<Gitignore> {
+        let mut loaded = self.loaded.lock().unwrap();
+        loaded
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let mut builder = GitignoreBuilder::new("");
+                for name in IGNORE_FILES {
+                    let file = dir.join(name);
+                    if file.is_file() {
+                        // keep whichever lines do parse
+                        let _ = builder.add(file);
+                    }
+                }
+                Arc::new(builder.build().unwrap_or_else(|_| Gitignore::empty()))
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns() {
+        let excludes = Excludes::new(&["node_modules", "/build", "*.bak", "!keep.bak"]).unwrap();
+        let root = Path::new("root");
+        assert!(excludes.is_excluded(root, Path::new("root/node_modules"), true));
+        assert!(excludes.is_excluded(root, Path::new("root/a/node_modules"), true));
+        assert!(excludes.is_excluded(root, Path::new("root/build"), true));
+        assert!(!excludes.is_excluded(root, Path::new("root/a/build"), true));
+        assert!(excludes.is_excluded(root, Path::new("root/a/link.bak"), false));
+        assert!(!excludes.is_excluded(root, Path::new("root/a/keep.bak"), false));
+        assert!(!excludes.is_excluded(root, Path::new("root"), true));
+    }
+
+    #[test]
+    fn test_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n*.tmp\n").unwrap();
+        fs::write(dir.path().join("sub/.ignore"), "!wanted.tmp\n").unwrap();
+
+        let excludes = Excludes::default().ignore_files(true);
+        let root = dir.path();
+        assert!(excludes.is_excluded(root, &root.join("target"), true));
+        assert!(!excludes.is_excluded(root, &root.join("target"), false));
+        assert!(excludes.is_excluded(root, &root.join("sub/x.tmp"), false));
+        assert!(!excludes.is_excluded(root, &root.join("sub/wanted.tmp"), false));
+        assert!(!Excludes::default().is_excluded(root, &root.join("a.tmp"), false));
+    }
+}
@@ -0,0 +1,298 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+use crate::plan::Action;
+use crate::replace_symlink;
+use crate::transform::Change;
+
+const JOURNAL_EXT: &str = "ndjson";
+const UNDONE_EXT: &str = "undone";
+
+/// One mutation made to the filesystem, as recorded in a journal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// Where journals are kept: `$SLINKY_STATE_DIR`, else `$XDG_STATE_HOME/slinky`,
+/// else `~/.local/state/slinky`.
+pub fn state_dir() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os("SLINKY_STATE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(dir) = env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir).join("slinky"));
+    }
+    let home = env::var_os("HOME").context("Cannot locate state directory; HOME is not set")?;
+    Ok(PathBuf::from(home).join(".local/state/slinky"))
+}
+
+/// Journals in the state directory that have not been undone, oldest first.
+pub fn list_journals() -> Result<Vec<PathBuf>> {
+    let dir = state_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut journals = fs::read_dir(&dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == JOURNAL_EXT))
+        .collect::<Vec<_>>();
+    journals.sort();
+    Ok(journals)
+}
+
+/// Records the mutations made by one run. The journal file is only created
+/// once the first entry is recorded. It can be shared between the threads
+/// applying changes.
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl Journal {
+    pub fn new() -> Result<Self> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let name = format!("{:016}-{}.{}", now.as_millis(), process::id(), JOURNAL_EXT);
+        Ok(Self {
+            path: state_dir()?.join(name),
+            file: Mutex::new(None),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Journal `action` and then make it, so that nothing is changed without
+    /// a record to undo it by. The record is taken back if the change fails.
+    pub fn apply(&self, action: &Action) -> Result<()> {
+        self.record(action).with_context(|| {
+            format!("{}: not changed, since it could not be journaled", action.origin.display())
+        })?;
+        action.apply().inspect_err(|_| {
+            // if even this fails, undo refuses the entry: the link won't be
+            // in the state it records
+            let _ = self.retract(action);
+        })
+    }
+
+    pub fn record(&self, action: &Action) -> Result<()> {
+        let entry = Entry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            action: action.clone(),
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        if file.is_none() {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let opened = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .with_context(|| format!("{}: cannot open journal", self.path.display()))?;
+            *file = Some(opened);
+        }
+        let file = file.as_mut().expect("journal file opened above");
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Take back the last record of `action`, for a change that couldn't be
+    /// made after all. A journal left empty is removed.
+    pub fn retract(&self, action: &Action) -> Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut entries = load(&self.path)?;
+        if let Some(i) = entries.iter().rposition(|entry| entry.action == *action) {
+            entries.remove(i);
+        }
+        if entries.is_empty() {
+            *file = None;
+            fs::remove_file(&self.path)?;
+        } else {
+            write_entries(&self.path, &entries)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("{}: cannot read journal", path.display()))?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}:{}: invalid journal entry", path.display(), i + 1))
+        })
+        .collect()
+}
+
+/// Rewrite a journal to hold only `remaining`; once nothing is left it is
+/// renamed aside so it isn't offered for undo again.
+pub fn retire(path: &Path, remaining: &[Entry]) -> Result<()> {
+    if remaining.is_empty() {
+        fs::rename(path, path.with_extension(UNDONE_EXT))?;
+    } else {
+        write_entries(path, remaining)?;
+    }
+    Ok(())
+}
+
+fn write_entries(path: &Path, entries: &[Entry]) -> Result<()> {
+    let mut text = String::new();
+    for entry in entries {
+        text.push_str(&serde_json::to_string(entry)?);
+        text.push('\n');
+    }
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// Restore the link described by `action` to the state it had before the action ran.
+pub fn undo(action: &Action) -> Result<()> {
+    let origin = &action.origin;
+    match &action.change {
+        Change::Retarget { target } => {
+            let current = fs::read_link(origin)
+                .with_context(|| format!("{}: no longer a symlink", origin.display()))?;
+            if &current != target {
+                anyhow::bail!(
+                    "{}: target changed since it was recorded (expected {}, found {})",
+                    origin.display(),
+                    target.display(),
+                    current.display()
+                );
+            }
+            replace_symlink(&action.old_target, origin)?;
+        }
+        Change::Remove => {
+            if fs::symlink_metadata(origin).is_ok() {
+                anyhow::bail!("{}: something already exists here", origin.display());
+            }
+            symlink(&action.old_target, origin)?;
+        }
+        Change::ReplaceWithHardlink { .. } => {
+            let metadata = fs::symlink_metadata(origin)?;
+            if !metadata.is_file() {
+                anyhow::bail!("{}: no longer a regular file", origin.display());
+            }
+            replace_symlink(&action.old_target, origin)?;
+        }
+        Change::ExpandToTree { target, .. } => {
+            if !fs::symlink_metadata(origin)?.is_dir() {
+                anyhow::bail!("{}: no longer a directory", origin.display());
+            }
+            // refuse to delete anything that wasn't created from the target
+            for entry in WalkDir::new(origin) {
+                let entry = entry?;
+                let rel = entry.path().strip_prefix(origin)?;
+                if fs::symlink_metadata(target.join(rel)).is_err() {
+                    anyhow::bail!(
+                        "{}: tree has been modified; {} is not in {}",
+                        origin.display(),
+                        rel.display(),
+                        target.display()
+                    );
+                }
+            }
+            fs::remove_dir_all(origin)?;
+            symlink(&action.old_target, origin)?;
+        }
+        Change::MoveTarget { target } => {
+            if fs::symlink_metadata(target).is_ok() {
+                anyhow::bail!("{}: something already exists here", target.display());
+            }
+            fs::rename(origin, target)?;
+            symlink(&action.old_target, origin)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_json_shape() {
+        let entry = Entry {
+            timestamp: 42,
+            action: Action {
+                origin: PathBuf::from("/a/link"),
+                old_target: PathBuf::from("b"),
+                change: Change::Retarget {
+                    target: PathBuf::from("c"),
+                },
+            },
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["timestamp"], 42);
+        assert_eq!(value["action"], "retarget");
+        assert_eq!(value["target"], "c");
+        assert_eq!(serde_json::from_str::<Entry>(&json).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_retract() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal {
+            path: dir.path().join("run.ndjson"),
+            file: Mutex::new(None),
+        };
+        let action = |name: &str| Action {
+            origin: dir.path().join(name),
+            old_target: PathBuf::from("x"),
+            change: Change::Remove,
+        };
+        journal.record(&action("a")).unwrap();
+        journal.record(&action("b")).unwrap();
+        journal.retract(&action("a")).unwrap();
+        let entries = load(journal.path()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, action("b"));
+
+        // an emptied journal isn't left to be undone, but can be started again
+        journal.retract(&action("b")).unwrap();
+        assert!(!journal.path().exists());
+        journal.record(&action("c")).unwrap();
+        assert_eq!(load(journal.path()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_undo_retarget() {
+        let dir = tempfile::tempdir().unwrap();
+        let origin = dir.path().join("link");
+        symlink("new", &origin).unwrap();
+        let action = Action {
+            origin: origin.clone(),
+            old_target: PathBuf::from("old"),
+            change: Change::Retarget {
+                target: PathBuf::from("new"),
+            },
+        };
+        undo(&action).unwrap();
+        assert_eq!(fs::read_link(&origin).unwrap(), PathBuf::from("old"));
+        // a second undo finds the link no longer matches
+        assert!(undo(&action).is_err());
+    }
+}
@@ -1,11 +1,36 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::*;
+use std::ffi::OsString;
 use std::fs;
+use std::io;
 use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
+use std::process::{self, ExitCode};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use walkdir::WalkDir;
 
 pub mod cli;
+pub mod confirm;
+pub mod exclude;
+pub mod journal;
+pub mod link;
+pub mod ln;
+pub mod manifest;
+pub mod output;
+pub mod parallel;
+pub mod plan;
+pub mod repair;
+pub mod snapshot;
+pub mod stow;
+pub mod transform;
+
+pub use confirm::Confirm;
+pub use exclude::Excludes;
+pub use journal::Journal;
+pub use link::{Chain, Hop, Link, LinkFilter, LinkScanner, Status};
+pub use plan::{Action, Plan};
+pub use stow::Stow;
+pub use transform::{Change, Outcome, Skip};
 
 pub fn tidy_path(path: &Path) -> PathBuf {
     let mut cleaned = PathBuf::new();
@@ -50,6 +75,53 @@ pub fn create_hard_link(target: &Path, origin: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Point `origin` at `target`, atomically replacing whatever non-directory is
+/// already there. The link is created under a temporary sibling name and
+/// renamed into place, so `origin` never goes missing.
+pub fn replace_symlink(target: &Path, origin: &Path) -> Result<()> {
+    replace_atomically(origin, |tmp| symlink(target, tmp))
+}
+
+/// Like [`replace_symlink`], but leaves a hardlink to `target` at `origin`.
+pub fn replace_with_hard_link(target: &Path, origin: &Path) -> Result<()> {
+    if target.is_dir() {
+        anyhow::bail!("cannot hard link a directory");
+    }
+    replace_atomically(origin, |tmp| fs::hard_link(target, tmp))
+}
+
+fn replace_atomically<F>(origin: &Path, create: F) -> Result<()>
+where
+    F: Fn(&Path) -> io::Result<()>,
+{
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = origin
+        .file_name()
+        .context("Could not get basename; origin path terminates in ..")?;
+    let dir = origin.parent().unwrap_or_else(|| Path::new("."));
+    let tmp = loop {
+        let mut name = OsString::from(".");
+        name.push(file_name);
+        name.push(format!(
+            ".slinky-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp = dir.join(name);
+        match create(&tmp) {
+            Ok(()) => break tmp,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    };
+    if let Err(e) = fs::rename(&tmp, origin) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -83,6 +155,27 @@ mod tests {
         assert_eq!(tidy_path(Path::new("a/./../b")), PathBuf::from("b"));
     }
 
+    #[test]
+    fn test_replace_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let origin = dir.path().join("link");
+        symlink("old", &origin).unwrap();
+        replace_symlink(Path::new("new"), &origin).unwrap();
+        assert_eq!(fs::read_link(&origin).unwrap(), PathBuf::from("new"));
+        // no temporary files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_replace_symlink_over_directory_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let origin = dir.path().join("sub");
+        fs::create_dir(&origin).unwrap();
+        assert!(replace_symlink(Path::new("new"), &origin).is_err());
+        assert!(origin.is_dir());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
     #[test]
     fn test_tidy_path_absolute() {
         assert_eq!(tidy_path(Path::new("/foo/bar")), PathBuf::from("/foo/bar"));
@@ -138,12 +231,76 @@ pub fn create_symlink_tree(target: &Path, origin: &Path) -> Result<()> {
     Ok(())
 }
 
-pub fn handle_operation<F>(op: F)
+/// Run `op`, reporting any error it returns. Returns whether it succeeded.
+pub fn handle_operation<F>(op: F) -> bool
 where
     F: FnOnce() -> Result<()>,
 {
-    if let Err(e) = op() {
-        eprintln!("{}: {}", "Error".red(), e);
+    match op() {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("{}: {}", "Error".red(), e);
+            false
+        }
+    }
+}
+
+/// Exit status when some links could not be handled.
+pub const EXIT_PARTIAL: u8 = 1;
+/// Exit status when the run as a whole failed.
+pub const EXIT_FATAL: u8 = 2;
+
+/// Tallies what happened to the links in a run, for the closing summary line
+/// and the exit status.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub done: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl Summary {
+    /// Like [`handle_operation`], but counts a failure if `op` errors.
+    pub fn handle_operation<F>(&mut self, op: F) -> bool
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let ok = handle_operation(op);
+        if !ok {
+            self.failed += 1;
+        }
+        ok
+    }
+
+    pub fn merge(&mut self, other: Summary) {
+        self.done += other.done;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Summary::default()
+    }
+
+    /// Print e.g. `tidy: 3 changed, 1 skipped, 0 failed` to stderr.
+    pub fn log(&self, cmd_name: &str, done_label: &str) {
+        let failed = format!("{} failed", self.failed);
+        eprintln!(
+            "{}: {} {}, {} skipped, {}",
+            cmd_name.bold(),
+            self.done,
+            done_label,
+            self.skipped,
+            if self.failed > 0 { failed.red() } else { failed.normal() }
+        );
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        if self.failed > 0 {
+            ExitCode::from(EXIT_PARTIAL)
+        } else {
+            ExitCode::SUCCESS
+        }
     }
 }
 
@@ -171,6 +328,10 @@ pub fn log_link_err(
     eprintln!("{} -> {}", link.cyan(), target.yellow());
 }
 
+pub fn log_warning(cmd: &str, msg: &str, link: &str, target: &str) {
+    log_link_err(Some(cmd.bold()), Some(msg.yellow()), link, target);
+}
+
 pub fn log_link(prefix: Option<ColoredString>, link: &str, target: &str) {
     if let Some(p) = prefix {
         print!("{}: ", p);
@@ -188,3 +349,86 @@ pub fn log_transformation(cmd_name: &str, link: &str, old: &str, new: &str) {
         new.yellow()
     );
 }
+
+pub fn log_change(cmd_name: &str, link: &Link, change: &Change) {
+    let origin = link.origin_str();
+    match change {
+        Change::Retarget { target } => {
+            log_transformation(cmd_name, &origin, &link.target_str(), &target.to_string_lossy())
+        }
+        Change::Remove => log_link(Some(cmd_name.bold().red()), &origin, &link.target_str()),
+        Change::ReplaceWithHardlink { .. } | Change::ExpandToTree { .. } | Change::MoveTarget { .. } => {
+            log_link(
+                Some(cmd_name.bold()),
+                &origin,
+                &link.target_resolved.to_string_lossy(),
+            )
+        }
+    }
+}
+
+/// Print `cmd: op: path -> target` for a stow step.
+pub fn log_op(cmd_name: &str, op: &stow::Op) {
+    print!("{}: {}: ", cmd_name.bold(), op);
+    match op {
+        stow::Op::Link { path, target } | stow::Op::Unlink { path, target } => {
+            println!("{} -> {}", path.to_string_lossy().cyan(), target.to_string_lossy().yellow())
+        }
+        stow::Op::Mkdir { path } | stow::Op::Rmdir { path } => println!("{}", path.to_string_lossy().cyan()),
+    }
+}
+
+/// Print a line of a diff-like report: `+` for a link made, `~` for one
+/// replaced, `-` for one removed, `!` for one that started or stopped
+/// dangling, and a space for one left alone.
+pub fn log_diff(sign: char, origin: &str, old: Option<&str>, new: &str) {
+    let sign = match sign {
+        '+' => "+".green(),
+        '~' => "~".yellow(),
+        '-' => "-".red(),
+        '!' => "!".magenta(),
+        _ => " ".normal(),
+    };
+    match old {
+        Some(old) => println!(
+            "{} {} -> ({} {} {})",
+            sign,
+            origin.cyan(),
+            old.dimmed(),
+            "=>".bright_white(),
+            new.yellow()
+        ),
+        None => println!("{} {} -> {}", sign, origin.cyan(), new.yellow()),
+    }
+}
+
+/// Print `status: origin -> hop (relative) -> hop (absolute) => end`.
+pub fn log_chain(chain: &Chain) {
+    let status = chain.status.to_string();
+    let status = match chain.status {
+        Status::Attached => status.green(),
+        Status::Denied => status.yellow(),
+        _ => status.red(),
+    };
+    print!("{}: {}", status, chain.hops[0].link.to_string_lossy().cyan());
+    for hop in &chain.hops {
+        let style = if hop.absolute { "(absolute)" } else { "(relative)" };
+        print!(" -> {} {}", hop.target.to_string_lossy().yellow(), style.dimmed());
+    }
+    match chain.status {
+        Status::Attached => {
+            let end = fs::canonicalize(chain.end()).unwrap_or_else(|_| chain.end().to_path_buf());
+            println!(" {} {}", "=>".bright_white(), end.to_string_lossy().green());
+        }
+        _ => println!(" {} {}", "=>".bright_white(), chain.end().to_string_lossy().red()),
+    }
+}
+
+pub fn log_skip(cmd_name: &str, link: &Link, skip: Skip) {
+    let msg = skip.to_string();
+    let msg = match skip {
+        Skip::AlreadyTidy | Skip::AlreadyFlat => msg.green(),
+        _ => msg.red(),
+    };
+    log_link_err(Some(cmd_name.bold()), Some(msg), &link.origin_str(), &link.target_str());
+}
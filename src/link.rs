@@ -0,0 +1,641 @@
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::cli::SlinkyCli;
+use crate::exclude::Excludes;
+use crate::parallel;
+
+/// Chains longer than this many symlinks are reported as [`Status::TooLong`]
+/// unless a scanner says otherwise.
+pub const DEFAULT_MAX_HOPS: usize = 40;
+
+/// Whether, and why not, a link's target can be reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Status {
+    Attached,
+    /// The target doesn't exist.
+    Dangling,
+    /// The chain of links comes back around to itself.
+    Looping,
+    /// The target exists, but we aren't allowed to look at it.
+    Denied,
+    /// The chain of links is longer than the hop limit.
+    TooLong,
+}
+
+/// One symlink along a chain: where it is, the target string stored in it, and
+/// where that leads.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Hop {
+    #[serde(serialize_with = "crate::output::lossy_path")]
+    pub link: PathBuf,
+    #[serde(serialize_with = "crate::output::lossy_path")]
+    pub target: PathBuf,
+    #[serde(serialize_with = "crate::output::lossy_path")]
+    pub resolved: PathBuf,
+    pub absolute: bool,
+}
+
+impl Hop {
+    fn new(link: &Path, target: &Path) -> Self {
+        Self {
+            link: link.to_path_buf(),
+            target: target.to_path_buf(),
+            resolved: resolve(link, target),
+            absolute: target.is_absolute(),
+        }
+    }
+}
+
+/// The symlinks passed through on the way from a link to whatever it finally
+/// points at. Symlinked directories along the way are left to the OS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chain {
+    pub hops: Vec<Hop>,
+    /// Whether the end of the chain can be reached. If not, the last hop is
+    /// where it breaks.
+    pub status: Status,
+}
+
+impl Chain {
+    /// Follow the chain from a link at `origin` holding `target`, one hop at a
+    /// time, giving up once it is longer than `max_hops`.
+    pub fn follow(origin: &Path, target: &Path, max_hops: usize) -> Self {
+        let mut hops = vec![Hop::new(origin, target)];
+        let mut seen = HashSet::from([canonical_location(origin)]);
+        loop {
+            let current = hops[hops.len() - 1].resolved.clone();
+            let Ok(next) = fs::read_link(&current) else {
+                break;
+            };
+            if !seen.insert(canonical_location(&current)) {
+                return Self {
+                    hops,
+                    status: Status::Looping,
+                };
+            }
+            if hops.len() >= max_hops {
+                return Self {
+                    hops,
+                    status: Status::TooLong,
+                };
+            }
+            hops.push(Hop::new(&current, &next));
+        }
+        let status = match fs::metadata(&hops[0].resolved) {
+            Ok(_) => Status::Attached,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Status::Denied,
+            // a loop through a directory somewhere along the way
+            Err(e) if e.raw_os_error() == Some(libc::ELOOP) => Status::Looping,
+            Err(_) => Status::Dangling,
+        };
+        Self { hops, status }
+    }
+
+    /// Read the symlink at `origin` and follow the chain from there.
+    pub fn read(origin: &Path, max_hops: usize) -> Result<Self> {
+        Ok(Self::follow(origin, &fs::read_link(origin)?, max_hops))
+    }
+
+    /// Where the chain ends, or breaks.
+    pub fn end(&self) -> &Path {
+        &self.hops[self.hops.len() - 1].resolved
+    }
+}
+
+/// A symlink found on disk, along with what it points to.
+#[derive(Debug, Clone)]
+pub struct Link {
+    /// Where the link lives.
+    pub origin: PathBuf,
+    /// The target string stored in the link, exactly as read.
+    pub target: PathBuf,
+    /// The target joined onto the link's parent directory (if relative).
+    pub target_resolved: PathBuf,
+    pub status: Status,
+    /// Whether the target is known not to exist: it is missing, or the chain
+    /// loops. Unreadable targets aren't dangling.
+    pub is_dangling: bool,
+    pub is_absolute: bool,
+    /// How many directories below the search root the link was found.
+    pub depth: usize,
+    /// The device of the search root the link was found under, if it was
+    /// found by walking.
+    pub root_dev: Option<u64>,
+}
+
+impl Link {
+    /// Describe a link at `origin` with the given target string, without touching
+    /// the link itself.
+    pub fn new(origin: &Path, target: &Path) -> Self {
+        let status = Chain::follow(origin, target, DEFAULT_MAX_HOPS).status;
+        let target_resolved = resolve(origin, target);
+        Self {
+            origin: origin.to_path_buf(),
+            target: target.to_path_buf(),
+            is_dangling: is_dangling(status, &target_resolved),
+            is_absolute: target.is_absolute(),
+            target_resolved,
+            status,
+            depth: 0,
+            root_dev: None,
+        }
+    }
+
+    /// Re-check the status against a different hop limit.
+    pub fn with_max_hops(mut self, max_hops: usize) -> Self {
+        if max_hops != DEFAULT_MAX_HOPS {
+            self.status = Chain::follow(&self.origin, &self.target, max_hops).status;
+            self.is_dangling = is_dangling(self.status, &self.target_resolved);
+        }
+        self
+    }
+
+    /// Read the symlink at `origin`. Fails if `origin` is not a symlink.
+    pub fn read(origin: &Path) -> Result<Self> {
+        Ok(Self::new(origin, &fs::read_link(origin)?))
+    }
+
+    /// The directory containing the link.
+    pub fn dir(&self) -> &Path {
+        self.origin.parent().unwrap_or_else(|| Path::new("."))
+    }
+
+    pub fn origin_str(&self) -> String {
+        self.origin.to_string_lossy().to_string()
+    }
+
+    pub fn target_str(&self) -> String {
+        self.target.to_string_lossy().to_string()
+    }
+}
+
+/// Criteria a link must satisfy to be yielded by a [`LinkScanner`].
+#[derive(Debug, Clone, Default)]
+pub struct LinkFilter {
+    pub only_dangling: bool,
+    pub only_attached: bool,
+    pub only_looping: bool,
+    pub only_absolute: bool,
+    pub only_relative: bool,
+    pub origin: Option<Regex>,
+    pub target: Option<Regex>,
+}
+
+impl LinkFilter {
+    pub fn from_cli(cli: &SlinkyCli) -> Result<Self> {
+        Ok(Self {
+            only_dangling: cli.only_dangling,
+            only_attached: cli.only_attached,
+            only_looping: cli.only_looping,
+            only_absolute: cli.only_absolute,
+            only_relative: cli.only_relative,
+            origin: cli.filter_origin.as_deref().map(Regex::new).transpose()?,
+            target: cli.filter_target.as_deref().map(Regex::new).transpose()?,
+        })
+    }
+
+    pub fn matches(&self, link: &Link) -> bool {
+        if self.only_dangling && !link.is_dangling {
+            return false;
+        }
+        if self.only_attached && link.status != Status::Attached {
+            return false;
+        }
+        if self.only_looping && link.status != Status::Looping {
+            return false;
+        }
+        if self.only_absolute && !link.is_absolute {
+            return false;
+        }
+        if self.only_relative && link.is_absolute {
+            return false;
+        }
+        if let Some(re) = &self.origin
+            && !re.is_match(&link.origin.to_string_lossy())
+        {
+            return false;
+        }
+        if let Some(re) = &self.target
+            && !re.is_match(&link.target.to_string_lossy())
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// Where a [`LinkScanner`] gets its candidate paths from.
+#[derive(Debug, Clone)]
+enum Source {
+    Walk(Vec<PathBuf>),
+    Paths(Vec<PathBuf>),
+}
+
+/// Walks one or more directory trees (or checks a given list of paths) and
+/// yields the symlinks that pass a [`LinkFilter`].
+#[derive(Debug, Clone)]
+pub struct LinkScanner {
+    source: Source,
+    max_depth: Option<usize>,
+    follow: bool,
+    one_file_system: bool,
+    excludes: Excludes,
+    max_hops: usize,
+    filter: LinkFilter,
+    jobs: Option<usize>,
+}
+
+impl LinkScanner {
+    fn with_source(source: Source) -> Self {
+        Self {
+            source,
+            max_depth: None,
+            follow: false,
+            one_file_system: false,
+            excludes: Excludes::default(),
+            max_hops: DEFAULT_MAX_HOPS,
+            filter: LinkFilter::default(),
+            jobs: None,
+        }
+    }
+
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self::with_source(Source::Walk(vec![root.into()]))
+    }
+
+    /// Also walk `root`. Links reached through more than one root are only
+    /// yielded the first time.
+    pub fn add_root(mut self, root: impl Into<PathBuf>) -> Self {
+        match &mut self.source {
+            Source::Walk(roots) => roots.push(root.into()),
+            Source::Paths(_) => self.source = Source::Walk(vec![root.into()]),
+        }
+        self
+    }
+
+    /// A scanner over an explicit list of link paths; nothing is walked.
+    pub fn from_paths(paths: Vec<PathBuf>) -> Self {
+        Self::with_source(Source::Paths(paths))
+    }
+
+    /// Build a scanner from the search path, depth and filter flags on the
+    /// command line, reading paths from stdin if --from-stdin0 was given.
+    pub fn from_cli(cli: &SlinkyCli) -> Result<Self> {
+        let scanner = if cli.from_stdin0 {
+            Self::from_paths(read_paths0(io::stdin().lock())?)
+        } else {
+            for path in &cli.paths {
+                if !path.exists() {
+                    anyhow::bail!("{}: No such file or directory", path.display());
+                }
+            }
+            Self::with_source(Source::Walk(cli.paths.clone()))
+        };
+        scanner.with_cli_options(cli)
+    }
+
+    /// Apply the depth, traversal and filter flags on the command line.
+    pub fn with_cli_options(self, cli: &SlinkyCli) -> Result<Self> {
+        Ok(self
+            .max_depth(cli.max_depth)
+            .follow(cli.follow)
+            .one_file_system(cli.one_file_system)
+            .excludes(Excludes::from_cli(cli)?)
+            .max_hops(cli.max_hops)
+            .jobs(cli.jobs)
+            .filter(LinkFilter::from_cli(cli)?))
+    }
+
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Descend into symlinked directories, yielding the links inside at the
+    /// path they were reached by.
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    /// Don't descend into directories on another device than the search root.
+    pub fn one_file_system(mut self, yes: bool) -> Self {
+        self.one_file_system = yes;
+        self
+    }
+
+    /// Skip excluded paths while walking, without descending into excluded
+    /// directories. Explicitly listed paths are not affected.
+    pub fn excludes(mut self, excludes: Excludes) -> Self {
+        self.excludes = excludes;
+        self
+    }
+
+    /// Report chains of more than `max_hops` links as [`Status::TooLong`].
+    pub fn max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    pub fn filter(mut self, filter: LinkFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Read links on a pool of threads (0 for one per CPU). Walked links are
+    /// then yielded sorted by origin path.
+    pub fn jobs(mut self, jobs: Option<usize>) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Iterate over matching links. Entries that can't be walked are skipped;
+    /// symlinks that can't be read, and listed paths that aren't symlinks, are
+    /// yielded as errors.
+    pub fn scan(&self) -> Box<dyn Iterator<Item = Result<Link>> + '_> {
+        if let Some(jobs) = self.jobs {
+            return Box::new(self.scan_parallel(jobs).into_iter());
+        }
+        let links: Box<dyn Iterator<Item = Result<Link>>> = match &self.source {
+            Source::Walk(roots) => Box::new(roots.iter().flat_map(move |root| {
+                let root_dev = device(root);
+                let same_device = if self.one_file_system { root_dev } else { None };
+                Walk::new(root, self.max_depth, self.follow, same_device, &self.excludes)
+                    .map(move |(path, depth)| self.read(&path, depth, root_dev))
+            })),
+            Source::Paths(paths) => Box::new(paths.iter().map(|path| self.read_listed(path))),
+        };
+        let mut seen = self.needs_dedup().then(HashSet::new);
+        Box::new(links.filter(move |link| match link {
+            Ok(link) => self.filter.matches(link) && first_sighting(seen.as_mut(), link),
+            Err(_) => true,
+        }))
+    }
+
+    /// Read the link at `path`, found `depth` levels below a root on `root_dev`.
+    fn read(&self, path: &Path, depth: usize, root_dev: Option<u64>) -> Result<Link> {
+        let mut link = Link::read(path)?.with_max_hops(self.max_hops);
+        link.depth = depth;
+        link.root_dev = root_dev;
+        Ok(link)
+    }
+
+    /// Read a link named explicitly (rather than found by walking).
+    fn read_listed(&self, path: &Path) -> Result<Link> {
+        if !path.is_symlink() {
+            anyhow::bail!("{}: not a symlink", path.display());
+        }
+        self.read(path, 0, None)
+    }
+
+    /// A link can be reached twice if roots overlap, or through a symlinked
+    /// directory when following them.
+    fn needs_dedup(&self) -> bool {
+        matches!(&self.source, Source::Walk(roots) if roots.len() > 1 || self.follow)
+    }
+
+    fn scan_parallel(&self, jobs: usize) -> Vec<Result<Link>> {
+        let keep = |link: &Result<Link>| match link {
+            Ok(link) => self.filter.matches(link),
+            Err(_) => true,
+        };
+        match &self.source {
+            Source::Walk(roots) => {
+                let mut seen = self.needs_dedup().then(HashSet::new);
+                let mut links = Vec::new();
+                for root in roots {
+                    let root_dev = device(root);
+                    let same_device = if self.one_file_system { root_dev } else { None };
+                    let excluded = |path: &Path, is_dir| self.excludes.is_excluded(root, path, is_dir);
+                    let mut found = parallel::walk_symlinks(
+                        root,
+                        self.max_depth,
+                        self.follow,
+                        same_device,
+                        jobs,
+                        excluded,
+                        |path, depth| {
+                            let link = self.read(path, depth, root_dev);
+                            keep(&link).then(|| (path.to_path_buf(), link))
+                        },
+                    );
+                    found.sort_by(|(a, _), (b, _)| a.cmp(b));
+                    links.extend(found.into_iter().map(|(_, link)| link).filter(|link| match link {
+                        Ok(link) => first_sighting(seen.as_mut(), link),
+                        Err(_) => true,
+                    }));
+                }
+                links
+            }
+            Source::Paths(paths) => parallel::map(paths.iter().collect(), jobs, |path| self.read_listed(path))
+                .into_iter()
+                .filter(keep)
+                .collect(),
+        }
+    }
+}
+
+/// Yields the path and depth of every symlink under a root. Each followed
+/// directory symlink gets its own walker, stacked on top of the one that found it.
+struct Walk<'a> {
+    root: &'a Path,
+    max_depth: Option<usize>,
+    follow: bool,
+    /// Only descend into directories on this device.
+    same_device: Option<u64>,
+    excludes: &'a Excludes,
+    stack: Vec<(walkdir::IntoIter, usize)>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(
+        root: &'a Path,
+        max_depth: Option<usize>,
+        follow: bool,
+        same_device: Option<u64>,
+        excludes: &'a Excludes,
+    ) -> Self {
+        let mut walk = Self {
+            root,
+            max_depth,
+            follow,
+            same_device,
+            excludes,
+            stack: Vec::new(),
+        };
+        walk.push(root, 0);
+        walk
+    }
+
+    fn push(&mut self, dir: &Path, depth: usize) {
+        let mut walker = WalkDir::new(dir).follow_links(false);
+        if depth > 0 {
+            // the symlink itself has already been yielded
+            walker = walker.min_depth(1);
+        }
+        if let Some(max) = self.max_depth {
+            walker = walker.max_depth(max - depth);
+        }
+        self.stack.push((walker.into_iter(), depth));
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = (PathBuf, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (walker, base) = self.stack.last_mut()?;
+            let base = *base;
+            let Some(entry) = walker.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let is_symlink = entry.path_is_symlink();
+            let depth = base + entry.depth();
+            let follow = self.follow && is_symlink && depth > base && entry.path().is_dir();
+            let is_dir = follow || (!is_symlink && entry.file_type().is_dir());
+            if self.excludes.is_excluded(self.root, entry.path(), is_dir) {
+                if is_dir && !is_symlink {
+                    walker.skip_current_dir();
+                }
+                continue;
+            }
+            let off_device = depth > base && is_dir && !on_device(entry.path(), self.same_device);
+            if off_device && !is_symlink {
+                walker.skip_current_dir();
+            }
+            if !is_symlink {
+                continue;
+            }
+            if follow
+                && !off_device
+                && self.max_depth.is_none_or(|max| depth < max)
+                && !loops_back(entry.path(), self.root)
+            {
+                self.push(entry.path(), depth);
+            }
+            return Some((entry.into_path(), depth));
+        }
+    }
+}
+
+fn device(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|m| m.dev())
+}
+
+/// Whether `path` (following symlinks) is on `dev`, or `dev` is None.
+pub(crate) fn on_device(path: &Path, dev: Option<u64>) -> bool {
+    dev.is_none_or(|dev| device(path) == Some(dev))
+}
+
+/// Whether the directory symlink at `link` resolves to a directory it was
+/// reached through on the way down from `root`, by device and inode.
+pub(crate) fn loops_back(link: &Path, root: &Path) -> bool {
+    let Ok(target) = fs::metadata(link) else {
+        return false;
+    };
+    link.ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(root))
+        .any(|dir| fs::metadata(dir).is_ok_and(|m| m.dev() == target.dev() && m.ino() == target.ino()))
+}
+
+/// Where `target` points when stored in a link at `origin`.
+fn resolve(origin: &Path, target: &Path) -> PathBuf {
+    if target.is_absolute() {
+        target.to_path_buf()
+    } else {
+        origin.parent().unwrap_or_else(|| Path::new(".")).join(target)
+    }
+}
+
+/// The canonical location of `path`, without following `path` itself if it is
+/// a symlink, only its parent directory.
+fn canonical_location(path: &Path) -> PathBuf {
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    match (fs::canonicalize(dir), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn is_dangling(status: Status, target_resolved: &Path) -> bool {
+    match status {
+        Status::Dangling | Status::Looping => true,
+        Status::Attached | Status::Denied => false,
+        Status::TooLong => !target_resolved.exists(),
+    }
+}
+
+/// Record `link` in `seen` by its canonical location, returning false if it was
+/// already there.
+fn first_sighting(seen: Option<&mut HashSet<PathBuf>>, link: &Link) -> bool {
+    match seen {
+        Some(seen) => seen.insert(canonical_location(&link.origin)),
+        None => true,
+    }
+}
+
+/// Split NUL-separated paths, as printed by `find -print0` or `slinky list -0`.
+pub fn read_paths0(mut input: impl Read) -> Result<Vec<PathBuf>> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    Ok(bytes
+        .split(|&b| b == 0)
+        .filter(|p| !p.is_empty())
+        .map(|p| PathBuf::from(OsStr::from_bytes(p)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_chain_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        symlink("d/x", path("d")).unwrap();
+        symlink("a", path("b")).unwrap();
+        symlink("b", path("a")).unwrap();
+
+        assert_eq!(Link::read(&path("a")).unwrap().status, Status::Looping);
+        // loops through a directory component are caught by the kernel
+        let link = Link::new(&path("via_dir"), Path::new("d/x"));
+        assert_eq!(link.status, Status::Looping);
+        assert!(link.is_dangling);
+        assert_eq!(Link::new(&path("gone"), Path::new("nothing")).status, Status::Dangling);
+        // unreadable targets aren't dangling, so they are never cleaned up as such
+        assert!(!is_dangling(Status::Denied, &path("nothing")));
+    }
+
+    #[test]
+    fn test_read_paths0() {
+        let paths = read_paths0(&b"a\0b c\0new\nline\0\0"[..]).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("a"),
+                PathBuf::from("b c"),
+                PathBuf::from("new\nline")
+            ]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::fs;
+use std::os::unix::fs::{MetadataExt, symlink};
+use std::path::{Path, PathBuf};
+
+use crate::cli::SlinkyLnCli;
+use crate::link::{Chain, DEFAULT_MAX_HOPS};
+use crate::transform::Change;
+use crate::{create_hard_link, create_hard_link_tree, create_symlink_tree, replace_symlink, replace_with_hard_link};
+
+/// The kinds of link `slinky-ln` makes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+pub enum Kind {
+    #[strum(serialize = "symlink")]
+    Symlink,
+    #[strum(serialize = "hardlink")]
+    Hardlink,
+    #[strum(serialize = "symlink tree")]
+    SymlinkTree,
+    #[strum(serialize = "hardlink tree")]
+    HardlinkTree,
+}
+
+/// A link as `slinky-ln` would make it, with its origin and target worked out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkSpec {
+    pub origin: PathBuf,
+    pub kind: Kind,
+    /// What a symlink will contain, or what a hardlink or tree is made from.
+    pub target: PathBuf,
+}
+
+impl LinkSpec {
+    /// Work out what `cli` asks for, looking up relative paths under `base`
+    /// (which may be empty, for the current directory). Fails if the target
+    /// doesn't exist and the link can't be made dangling.
+    pub fn from_cli(cli: &SlinkyLnCli, base: &Path) -> Result<Self> {
+        Self::resolve(cli, base, true)
+    }
+
+    /// Like [`LinkSpec::from_cli`], but the link is made at the origin itself
+    /// even if it names a directory, so that making it twice is a no-op.
+    pub fn exact(cli: &SlinkyLnCli, base: &Path) -> Result<Self> {
+        Self::resolve(cli, base, false)
+    }
+
+    fn resolve(cli: &SlinkyLnCli, base: &Path, into_dir: bool) -> Result<Self> {
+        let raw_target_string = cli.target.as_deref().context("No target given")?;
+
+        // dereference target string if necessary
+        let (base_target_path, base_target_string) = if cli.dereference {
+            let resolved_path = dereference_symlink(&base.join(raw_target_string));
+            let resolved_string = resolved_path.to_string_lossy().to_string();
+            (resolved_path, resolved_string)
+        } else {
+            (base.join(raw_target_string), raw_target_string.to_string())
+        };
+
+        // determine where the new link will be created (the 'origin')
+        let origin_input = base.join(cli.origin.as_deref().unwrap_or("."));
+        let origin = if into_dir && origin_input.is_dir() {
+            let resolved_target = if base_target_path.exists() {
+                fs::canonicalize(&base_target_path)?
+            } else {
+                base_target_path.clone()
+            };
+            let file_name = resolved_target
+                .file_name()
+                .context("Could not get basename; target path terminates in ..")?;
+            origin_input.join(file_name)
+        } else {
+            origin_input
+        };
+
+        if !base_target_path.exists() {
+            if cli.tree || cli.hard {
+                anyhow::bail!("Target does not exist; cannot create {}", if cli.tree { "tree" } else { "hardlink" });
+            } else if !cli.allow_dangling {
+                anyhow::bail!("Target does not exist; refusing to create dangling symlink without --allow-dangling");
+            }
+        }
+
+        let kind = match (cli.tree, cli.hard) {
+            (true, true) => Kind::HardlinkTree,
+            (true, false) => Kind::SymlinkTree,
+            (false, true) => Kind::Hardlink,
+            (false, false) => Kind::Symlink,
+        };
+        if kind != Kind::Symlink {
+            return Ok(Self {
+                origin,
+                kind,
+                target: base_target_path,
+            });
+        }
+
+        // transform target string for --relative and --absolute if necessary
+        let target = if cli.absolute {
+            fs::canonicalize(&base_target_path)?
+        } else if cli.relative {
+            let abs_target = fs::canonicalize(&base_target_path)?;
+            let origin_parent = origin
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            let abs_origin_parent = fs::canonicalize(origin_parent)?;
+            pathdiff::diff_paths(&abs_target, &abs_origin_parent).context("Failed to calculate relative path")?
+        } else {
+            PathBuf::from(base_target_string)
+        };
+        Ok(Self { origin, kind, target })
+    }
+
+    /// Make the link, first getting rid of whatever is at the origin if
+    /// `replace` is set. Symlinks and hardlinks are swapped in atomically;
+    /// trees need the old file gone first.
+    pub fn create(&self, replace: bool) -> Result<()> {
+        match self.kind {
+            Kind::SymlinkTree | Kind::HardlinkTree => {
+                if replace {
+                    fs::remove_file(&self.origin)?;
+                }
+                if self.kind == Kind::HardlinkTree {
+                    create_hard_link_tree(&self.target, &self.origin)
+                } else {
+                    create_symlink_tree(&self.target, &self.origin)
+                }
+            }
+            Kind::Hardlink if replace => replace_with_hard_link(&self.target, &self.origin),
+            Kind::Hardlink => create_hard_link(&self.target, &self.origin),
+            Kind::Symlink if replace => replace_symlink(&self.target, &self.origin),
+            Kind::Symlink => Ok(symlink(&self.target, &self.origin)?),
+        }
+    }
+
+    /// The change that turns a symlink at the origin into this link.
+    pub fn change(&self) -> Change {
+        let target = self.target.clone();
+        match self.kind {
+            Kind::Symlink => Change::Retarget { target },
+            Kind::Hardlink => Change::ReplaceWithHardlink { target },
+            Kind::SymlinkTree => Change::ExpandToTree { target, hard: false },
+            Kind::HardlinkTree => Change::ExpandToTree { target, hard: true },
+        }
+    }
+
+    /// Whether the link is already there as it would be made. Trees of a
+    /// directory count as made if there is a directory at the origin.
+    pub fn exists(&self) -> bool {
+        let Ok(meta) = fs::symlink_metadata(&self.origin) else {
+            return false;
+        };
+        let same_file = |a: &Path, b: &Path| match (fs::metadata(a), fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        };
+        match self.kind {
+            Kind::Symlink => fs::read_link(&self.origin).is_ok_and(|target| target == self.target),
+            _ if self.target.is_dir() => meta.is_dir(),
+            Kind::SymlinkTree => meta.is_symlink() && same_file(&self.origin, &self.target),
+            Kind::Hardlink | Kind::HardlinkTree => meta.is_file() && same_file(&self.origin, &self.target),
+        }
+    }
+}
+
+/// One link listed in the input of `slinky-ln --batch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchEntry {
+    /// The line, or for NUL-separated input the pair, it came from, counting
+    /// from 1.
+    pub number: usize,
+    fields: Vec<String>,
+}
+
+impl BatchEntry {
+    /// Split batch input into its entries: `target<TAB>origin[<TAB>flags]`
+    /// lines, skipping blank ones and `#` comments, or if `null` is set,
+    /// NUL-separated target and origin pairs.
+    pub fn parse_all(input: &str, null: bool) -> Vec<Self> {
+        if null {
+            let mut fields: Vec<&str> = input.split('\0').collect();
+            if fields.last() == Some(&"") {
+                fields.pop();
+            }
+            fields
+                .chunks(2)
+                .enumerate()
+                .map(|(i, pair)| Self {
+                    number: i + 1,
+                    fields: pair.iter().map(|field| field.to_string()).collect(),
+                })
+                .collect()
+        } else {
+            input
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+                .map(|(i, line)| Self {
+                    number: i + 1,
+                    fields: line.split('\t').map(str::to_string).collect(),
+                })
+                .collect()
+        }
+    }
+
+    /// `base` with this entry's target and origin, and its flags on top.
+    /// Flags choosing what kind of link to make, like `--relative` or
+    /// `--hard`, replace those of `base` rather than adding to them.
+    pub fn to_cli(&self, base: &SlinkyLnCli) -> Result<SlinkyLnCli> {
+        let (target, origin, flags) = match self.fields.as_slice() {
+            [target, origin] => (target, origin, ""),
+            [target, origin, flags] => (target, origin, flags.as_str()),
+            _ => anyhow::bail!("expected a target and an origin, and optionally flags, separated by tabs"),
+        };
+        // let clap check the flags, and whether they conflict, as it does on the command line
+        let args = ["slinky-ln"]
+            .into_iter()
+            .chain(flags.split_whitespace())
+            .chain(["--", target, origin]);
+        let line = SlinkyLnCli::try_parse_from(args).map_err(|e| {
+            let msg = e.to_string();
+            anyhow::anyhow!("{}", msg.lines().next().unwrap_or_default().trim_start_matches("error: "))
+        })?;
+
+        let sets_kind = line.absolute || line.relative || line.allow_dangling || line.hard || line.tree;
+        let kind = if sets_kind { &line } else { base };
+        Ok(SlinkyLnCli {
+            target: line.target.clone(),
+            origin: line.origin.clone(),
+            force: base.force || line.force,
+            absolute: kind.absolute,
+            relative: kind.relative,
+            dereference: base.dereference || line.dereference,
+            allow_dangling: kind.allow_dangling,
+            hard: kind.hard,
+            tree: kind.tree,
+            verbose: base.verbose,
+            dry_run: base.dry_run,
+            batch: None,
+            null: false,
+        })
+    }
+}
+
+fn dereference_symlink(path: &Path) -> PathBuf {
+    if !path.is_symlink() {
+        return path.to_path_buf();
+    }
+    match fs::canonicalize(path) {
+        Ok(resolved) => resolved,
+        // follow the broken chain as far as it goes
+        Err(_) => match Chain::read(path, DEFAULT_MAX_HOPS) {
+            Ok(chain) => chain.end().to_path_buf(),
+            Err(_) => path.to_path_buf(),
+        },
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli::SlinkyLnCli;
+
+/// The links a machine should have, read from TOML:
+///
+/// ```toml
+/// # directories where `sync --prune` removes symlinks not listed here
+/// roots = ["~/.config"]
+///
+/// [[link]]
+/// origin = "~/.config/nvim"
+/// target = "nvim"
+/// relative = true
+/// ```
+///
+/// Each `[[link]]` takes the options of `slinky-ln`: `relative`, `absolute`,
+/// `hard`, `tree`, `allow-dangling`, `force` and `dereference`. A leading `~`
+/// stands for the home directory.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub roots: Vec<String>,
+    #[serde(default, rename = "link")]
+    pub links: Vec<Entry>,
+}
+
+/// One `[[link]]` of a manifest.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Entry {
+    pub origin: String,
+    pub target: String,
+    #[serde(default)]
+    pub relative: bool,
+    #[serde(default)]
+    pub absolute: bool,
+    #[serde(default)]
+    pub hard: bool,
+    #[serde(default)]
+    pub tree: bool,
+    #[serde(default)]
+    pub allow_dangling: bool,
+    #[serde(default)]
+    pub force: bool,
+    #[serde(default)]
+    pub dereference: bool,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("{}: cannot read manifest", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("{}: invalid manifest", path.display()))
+    }
+
+    /// The directories listed under `roots`.
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.roots.iter().map(|root| PathBuf::from(expand_home(root))).collect()
+    }
+}
+
+impl Entry {
+    /// The `slinky-ln` invocation this entry stands for.
+    pub fn to_cli(&self) -> Result<SlinkyLnCli> {
+        let styles = [self.absolute, self.relative, self.allow_dangling, self.hard || self.tree];
+        if styles.iter().filter(|&&set| set).count() > 1 {
+            anyhow::bail!(
+                "{}: only one of absolute, relative, allow-dangling, or hard and tree may be set",
+                self.origin
+            );
+        }
+        Ok(SlinkyLnCli {
+            target: Some(expand_home(&self.target)),
+            origin: Some(expand_home(&self.origin)),
+            force: self.force,
+            absolute: self.absolute,
+            relative: self.relative,
+            dereference: self.dereference,
+            allow_dangling: self.allow_dangling,
+            hard: self.hard,
+            tree: self.tree,
+            verbose: false,
+            dry_run: false,
+            batch: None,
+            null: false,
+        })
+    }
+}
+
+/// Replace a leading `~` with $HOME, if it's set.
+fn expand_home(path: &str) -> String {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => return path.to_string(),
+    };
+    match std::env::var("HOME") {
+        Ok(home) => format!("{}{}", home, rest),
+        Err(_) => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            roots = ["links"]
+
+            [[link]]
+            origin = "links/a"
+            target = "a.txt"
+            relative = true
+
+            [[link]]
+            origin = "links/b"
+            target = "b"
+            hard = true
+            tree = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(manifest.roots(), vec![PathBuf::from("links")]);
+        let cli = manifest.links[0].to_cli().unwrap();
+        assert_eq!((cli.origin.as_deref(), cli.target.as_deref()), (Some("links/a"), Some("a.txt")));
+        assert!(cli.relative && !cli.hard);
+        assert!(manifest.links[1].to_cli().is_ok());
+
+        let entry = Entry {
+            relative: true,
+            hard: true,
+            ..manifest.links[0].clone()
+        };
+        assert!(entry.to_cli().is_err());
+        assert!(toml::from_str::<Manifest>("[[link]]\norigin = \"a\"\ntarget = \"b\"\nsoft = true\n").is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Serialize, Serializer};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cli::OutputFormat;
+use crate::link::{Hop, Link, Status};
+use crate::transform::Change;
+
+/// A link and what was done to it, in a form meant for scripts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Record {
+    #[serde(serialize_with = "lossy_path")]
+    pub origin: PathBuf,
+    #[serde(serialize_with = "lossy_path")]
+    pub target: PathBuf,
+    #[serde(serialize_with = "lossy_path")]
+    pub target_resolved: PathBuf,
+    pub dangling: bool,
+    pub absolute: bool,
+    pub depth: usize,
+    pub action: String,
+    /// The target string after a retarget.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "lossy_path_opt")]
+    pub new_target: Option<PathBuf>,
+    pub status: Status,
+    /// Every link passed through on the way to the final target. JSON and
+    /// NDJSON only.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hops: Vec<Hop>,
+}
+
+impl Record {
+    pub fn new(link: &Link, action: &str) -> Self {
+        Self {
+            origin: link.origin.clone(),
+            target: link.target.clone(),
+            target_resolved: link.target_resolved.clone(),
+            dangling: link.is_dangling,
+            absolute: link.is_absolute,
+            depth: link.depth,
+            action: action.to_string(),
+            new_target: None,
+            status: link.status,
+            hops: Vec::new(),
+        }
+    }
+
+    pub fn with_hops(mut self, hops: Vec<Hop>) -> Self {
+        self.hops = hops;
+        self
+    }
+
+    pub fn for_change(link: &Link, change: &Change) -> Self {
+        let mut record = Self::new(link, &change.to_string());
+        if let Change::Retarget { target } = change {
+            record.new_target = Some(target.clone());
+        }
+        record
+    }
+
+    /// The record's fields in the column order used by tsv and null output.
+    fn fields(&self) -> [String; 9] {
+        [
+            self.origin.to_string_lossy().to_string(),
+            self.target.to_string_lossy().to_string(),
+            self.target_resolved.to_string_lossy().to_string(),
+            self.dangling.to_string(),
+            self.absolute.to_string(),
+            self.depth.to_string(),
+            self.action.clone(),
+            self.new_target
+                .as_ref()
+                .map(|t| t.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            self.status.to_string(),
+        ]
+    }
+}
+
+/// Paths as text with invalid UTF-8 replaced, the same as tsv and null
+/// output, so a record can always be written in full.
+pub(crate) fn lossy_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+fn lossy_path_opt<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => lossy_path(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn escape_tsv(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes records to stdout in a machine-readable format. JSON output is
+/// buffered until [`Printer::finish`] so it forms a single array.
+pub struct Printer {
+    format: OutputFormat,
+    pending: Vec<Record>,
+}
+
+impl Printer {
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Whether records should go through the printer instead of the colored text loggers.
+    pub fn is_structured(&self) -> bool {
+        self.format != OutputFormat::Text
+    }
+
+    pub fn emit(&mut self, record: Record) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        match self.format {
+            OutputFormat::Text => {
+                writeln!(out, "{} -> {}", record.origin.display(), record.target.display())
+            }
+            OutputFormat::Json => {
+                self.pending.push(record);
+                Ok(())
+            }
+            OutputFormat::Ndjson => {
+                // serialize first, so a failure can't leave half a line behind
+                let mut line = serde_json::to_vec(&record)?;
+                line.push(b'\n');
+                out.write_all(&line)
+            }
+            OutputFormat::Tsv => {
+                let fields = record.fields().map(|f| escape_tsv(&f));
+                writeln!(out, "{}", fields.join("\t"))
+            }
+            OutputFormat::Null => {
+                for field in record.fields() {
+                    out.write_all(field.as_bytes())?;
+                    out.write_all(b"\0")?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            let mut out = io::stdout().lock();
+            serde_json::to_writer_pretty(&mut out, &self.pending)?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_tsv() {
+        assert_eq!(escape_tsv("plain"), "plain");
+        assert_eq!(escape_tsv("a\tb\nc\\d"), "a\\tb\\nc\\\\d");
+    }
+
+    #[test]
+    fn test_record_for_retarget() {
+        let link = Link::new(&PathBuf::from("dir/link"), &PathBuf::from("../target"));
+        let record = Record::for_change(
+            &link,
+            &Change::Retarget {
+                target: PathBuf::from("/target"),
+            },
+        );
+        assert_eq!(record.action, "retarget");
+        assert_eq!(record.target_resolved, PathBuf::from("dir/../target"));
+        assert_eq!(record.new_target, Some(PathBuf::from("/target")));
+        assert!(!record.absolute);
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+
+use crate::link::{loops_back, on_device};
+
+/// Resolve a `--jobs` value, where 0 means one thread per available CPU.
+pub fn thread_count(jobs: usize) -> usize {
+    if jobs == 0 {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        jobs
+    }
+}
+
+/// Apply `f` to every item on a pool of `jobs` threads, returning the results
+/// in the order of the input.
+pub fn map<T, R, F>(items: Vec<T>, jobs: usize, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let jobs = thread_count(jobs).min(items.len()).max(1);
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let mut results = thread::scope(|s| {
+        let workers = (0..jobs)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let next = queue.lock().unwrap().next();
+                        match next {
+                            Some((i, item)) => done.push((i, f(item))),
+                            None => return done,
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Apply `f` to every item on a pool of `jobs` threads, where items sharing a
+/// key are handed to `f` one at a time, in input order, on the same thread.
+/// Results come back in the order of the input.
+pub fn map_grouped<T, K, R, F>(items: Vec<T>, jobs: usize, key: impl Fn(&T) -> K, f: F) -> Vec<R>
+where
+    T: Send,
+    K: Ord,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let mut groups: BTreeMap<K, Vec<(usize, T)>> = BTreeMap::new();
+    for (i, item) in items.into_iter().enumerate() {
+        groups.entry(key(&item)).or_default().push((i, item));
+    }
+    let mut results = map(groups.into_values().collect(), jobs, |group| {
+        group
+            .into_iter()
+            .map(|(i, item)| (i, f(item)))
+            .collect::<Vec<_>>()
+    })
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+struct WalkState {
+    dirs: Vec<(PathBuf, usize)>,
+    active: usize,
+    /// Set once a worker panics, so the others stop instead of walking on.
+    aborted: bool,
+}
+
+/// A directory a worker has taken from the queue. Dropping it hands back the
+/// subdirectories found and wakes the other workers, even if visiting the
+/// directory panicked, so none of them is left waiting for it.
+struct Taken<'a> {
+    state: &'a Mutex<WalkState>,
+    wakeup: &'a Condvar,
+    subdirs: Vec<(PathBuf, usize)>,
+}
+
+impl Drop for Taken<'_> {
+    fn drop(&mut self) {
+        let mut st = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if thread::panicking() {
+            st.aborted = true;
+            st.dirs.clear();
+        } else if !st.aborted {
+            st.dirs.append(&mut self.subdirs);
+        }
+        st.active -= 1;
+        self.wakeup.notify_all();
+    }
+}
+
+/// Walk `root` on a pool of `jobs` threads, calling `visit` with the path and
+/// depth of every symlink found. Symlinked directories are only descended into
+/// if `follow` is set, and never when they lead back to one of their own
+/// ancestors. Given `same_device`, directories on other devices aren't
+/// descended into either. Paths for which `excluded(path, is_dir)` holds are skipped, along
+/// with everything under them, as are unreadable directories. Like `WalkDir`, a
+/// symlinked root is followed.
+pub fn walk_symlinks<R, E, F>(
+    root: &Path,
+    max_depth: Option<usize>,
+    follow: bool,
+    same_device: Option<u64>,
+    jobs: usize,
+    excluded: E,
+    visit: F,
+) -> Vec<R>
+where
+    R: Send,
+    E: Fn(&Path, bool) -> bool + Sync,
+    F: Fn(&Path, usize) -> Option<R> + Sync,
+{
+    let mut found = Vec::new();
+    if root.is_symlink()
+        && let Some(r) = visit(root, 0)
+    {
+        found.push(r);
+    }
+    if !root.is_dir() || max_depth == Some(0) {
+        return found;
+    }
+
+    let state = Mutex::new(WalkState {
+        dirs: vec![(root.to_path_buf(), 0)],
+        active: 0,
+        aborted: false,
+    });
+    let wakeup = Condvar::new();
+
+    let worker = || {
+        let mut found = Vec::new();
+        loop {
+            let (dir, depth) = {
+                let mut st = state.lock().unwrap();
+                loop {
+                    if let Some(next) = st.dirs.pop() {
+                        st.active += 1;
+                        break next;
+                    }
+                    if st.active == 0 || st.aborted {
+                        wakeup.notify_all();
+                        return found;
+                    }
+                    st = wakeup.wait(st).unwrap();
+                }
+            };
+
+            let mut taken = Taken {
+                state: &state,
+                wakeup: &wakeup,
+                subdirs: Vec::new(),
+            };
+            let subdirs = &mut taken.subdirs;
+            if let Ok(entries) = fs::read_dir(&dir) {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    let Ok(file_type) = entry.file_type() else {
+                        continue;
+                    };
+                    let path = entry.path();
+                    let follow = follow && file_type.is_symlink() && path.is_dir();
+                    let is_dir = follow || file_type.is_dir();
+                    if excluded(&path, is_dir) {
+                        continue;
+                    }
+                    let descend = is_dir
+                        && max_depth.is_none_or(|max| depth + 1 < max)
+                        && on_device(&path, same_device);
+                    if file_type.is_symlink() {
+                        if let Some(r) = visit(&path, depth + 1) {
+                            found.push(r);
+                        }
+                        if follow && descend && !loops_back(&path, root) {
+                            subdirs.push((path, depth + 1));
+                        }
+                    } else if descend {
+                        subdirs.push((path, depth + 1));
+                    }
+                }
+            }
+        }
+    };
+
+    thread::scope(|s| {
+        let workers = (0..thread_count(jobs))
+            .map(|_| s.spawn(worker))
+            .collect::<Vec<_>>();
+        for w in workers {
+            found.extend(w.join().unwrap());
+        }
+    });
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_map_preserves_order() {
+        let out = map((0..100).collect(), 4, |n: i32| n * 2);
+        assert_eq!(out, (0..100).map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_map_grouped_serializes_groups() {
+        let items = vec![(1, 'a'), (2, 'b'), (1, 'c'), (2, 'd'), (1, 'e')];
+        let seen = Mutex::new(Vec::new());
+        let out = map_grouped(items, 4, |(k, _)| *k, |(k, c)| {
+            seen.lock().unwrap().push((k, c));
+            c
+        });
+        assert_eq!(out, vec!['a', 'b', 'c', 'd', 'e']);
+        let seen = seen.into_inner().unwrap();
+        let ones = seen.iter().filter(|(k, _)| *k == 1).map(|(_, c)| *c).collect::<Vec<_>>();
+        assert_eq!(ones, vec!['a', 'c', 'e']);
+    }
+
+    #[test]
+    fn test_walk_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        symlink("x", dir.path().join("top")).unwrap();
+        symlink("x", dir.path().join("a/b/deep")).unwrap();
+        symlink("a", dir.path().join("dirlink")).unwrap();
+
+        let mut all = walk_symlinks(dir.path(), None, false, None, 3, |_, _| false, |p, d| Some((p.to_path_buf(), d)));
+        all.sort();
+        assert_eq!(
+            all,
+            vec![
+                (dir.path().join("a/b/deep"), 3),
+                (dir.path().join("dirlink"), 1),
+                (dir.path().join("top"), 1),
+            ]
+        );
+
+        let shallow = walk_symlinks(dir.path(), Some(2), false, None, 3, |_, _| false, |p, _| Some(p.to_path_buf()));
+        assert_eq!(shallow.len(), 2);
+    }
+
+    #[test]
+    fn test_walk_symlinks_panic() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["a", "b", "c"] {
+            fs::create_dir_all(dir.path().join(sub).join("deeper")).unwrap();
+            symlink("x", dir.path().join(sub).join("deeper/link")).unwrap();
+        }
+        // one worker panics; the rest must not wait on it forever
+        let walked = std::panic::catch_unwind(|| {
+            walk_symlinks(dir.path(), None, false, None, 3, |_, _| false, |p, _| {
+                assert!(!p.starts_with(dir.path().join("a")), "visited {}", p.display());
+                Some(())
+            })
+        });
+        assert!(walked.is_err());
+    }
+
+    #[test]
+    fn test_walk_symlinks_follow() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("real/sub")).unwrap();
+        symlink("x", dir.path().join("real/sub/deep")).unwrap();
+        symlink("..", dir.path().join("real/sub/up")).unwrap();
+        symlink("real", dir.path().join("via")).unwrap();
+
+        let mut all = walk_symlinks(dir.path(), None, true, None, 3, |_, _| false, |p, d| {
+            Some((p.strip_prefix(dir.path()).unwrap().to_path_buf(), d))
+        });
+        all.sort();
+        assert_eq!(
+            all,
+            vec![
+                (PathBuf::from("real/sub/deep"), 3),
+                (PathBuf::from("real/sub/up"), 3),
+                (PathBuf::from("via"), 1),
+                (PathBuf::from("via/sub/deep"), 3),
+                (PathBuf::from("via/sub/up"), 3),
+            ]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::link::Link;
+use crate::transform::Change;
+
+const PLAN_VERSION: u32 = 1;
+
+/// A planned change to one symlink, recorded with the target it had when planned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Action {
+    #[serde(with = "os_path")]
+    pub origin: PathBuf,
+    #[serde(with = "os_path")]
+    pub old_target: PathBuf,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+impl Action {
+    /// Record `change` against `link`. Paths on disk are made absolute so the
+    /// action can be applied from any working directory; retarget strings are
+    /// kept exactly as they will be written.
+    pub fn new(link: &Link, change: Change) -> Result<Self> {
+        let change = match change {
+            Change::Retarget { target } => Change::Retarget { target },
+            Change::Remove => Change::Remove,
+            Change::ReplaceWithHardlink { target } => Change::ReplaceWithHardlink {
+                target: std::path::absolute(target)?,
+            },
+            Change::ExpandToTree { target, hard } => Change::ExpandToTree {
+                target: std::path::absolute(target)?,
+                hard,
+            },
+            Change::MoveTarget { target } => Change::MoveTarget {
+                target: std::path::absolute(target)?,
+            },
+        };
+        Ok(Self {
+            origin: std::path::absolute(&link.origin)?,
+            old_target: link.target.clone(),
+            change,
+        })
+    }
+
+    /// Re-read the link and check it still points where it did when planned.
+    pub fn check(&self) -> Result<Link> {
+        let link = Link::read(&self.origin)
+            .with_context(|| format!("{}: no longer a symlink", self.origin.display()))?;
+        if link.target != self.old_target {
+            anyhow::bail!(
+                "{}: target changed since plan was made (expected {}, found {})",
+                self.origin.display(),
+                self.old_target.display(),
+                link.target.display()
+            );
+        }
+        Ok(link)
+    }
+
+    /// Check the link, then make the change. Changes that need the target
+    /// are refused if it has gone since, e.g. moved by an earlier action.
+    pub fn apply(&self) -> Result<()> {
+        let link = self.check()?;
+        let needs_target = matches!(
+            self.change,
+            Change::MoveTarget { .. } | Change::ReplaceWithHardlink { .. } | Change::ExpandToTree { .. }
+        );
+        if needs_target && link.is_dangling {
+            anyhow::bail!("{}: target no longer exists", self.origin.display());
+        }
+        self.change.apply(&self.origin)
+    }
+}
+
+/// Paths as JSON strings where they are valid UTF-8, and as arrays of bytes
+/// where they aren't, so that any link can be planned and journaled.
+pub(crate) mod os_path {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::ffi::OsString;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Text(String),
+        Bytes(Vec<u8>),
+    }
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        match path.to_str() {
+            Some(text) => serializer.serialize_str(text),
+            None => path.as_os_str().as_bytes().serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Text(text) => PathBuf::from(text),
+            Repr::Bytes(bytes) => PathBuf::from(OsString::from_vec(bytes)),
+        })
+    }
+}
+
+/// An ordered list of actions that can be saved, reviewed and applied later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    pub actions: Vec<Action>,
+}
+
+impl Default for Plan {
+    fn default() -> Self {
+        Self {
+            version: PLAN_VERSION,
+            actions: Vec::new(),
+        }
+    }
+}
+
+impl Plan {
+    pub fn push(&mut self, action: Action) {
+        self.actions.push(action);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Load a plan from a JSON file, or from stdin if `path` is `-`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = if path == Path::new("-") {
+            io::read_to_string(io::stdin())?
+        } else {
+            fs::read_to_string(path).with_context(|| format!("{}: cannot read plan", path.display()))?
+        };
+        let plan: Plan = serde_json::from_str(&text)
+            .with_context(|| format!("{}: invalid plan", path.display()))?;
+        if plan.version != PLAN_VERSION {
+            anyhow::bail!(
+                "{}: unsupported plan version {}",
+                path.display(),
+                plan.version
+            );
+        }
+        Ok(plan)
+    }
+
+    /// Save the plan as JSON, or print it to stdout if `path` is `-`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        if path == Path::new("-") {
+            io::stdout().write_all(json.as_bytes())?;
+        } else {
+            fs::write(path, json).with_context(|| format!("{}: cannot write plan", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_json_shape() {
+        let action = Action {
+            origin: PathBuf::from("/a/link"),
+            old_target: PathBuf::from("../b"),
+            change: Change::ExpandToTree {
+                target: PathBuf::from("/b"),
+                hard: true,
+            },
+        };
+        let json = serde_json::to_value(&action).unwrap();
+        assert_eq!(json["action"], "expand-to-tree");
+        assert_eq!(json["origin"], "/a/link");
+        assert_eq!(json["old_target"], "../b");
+        assert_eq!(json["hard"], true);
+    }
+
+    #[test]
+    fn test_plan_roundtrip() {
+        let mut plan = Plan::default();
+        plan.push(Action {
+            origin: PathBuf::from("/a/link"),
+            old_target: PathBuf::from("b"),
+            change: Change::Remove,
+        });
+        let json = serde_json::to_string(&plan).unwrap();
+        assert_eq!(serde_json::from_str::<Plan>(&json).unwrap(), plan);
+
+        // paths that aren't UTF-8 are kept as bytes
+        use std::os::unix::ffi::OsStringExt;
+        plan.actions[0].origin = PathBuf::from(std::ffi::OsString::from_vec(b"/a/l\xff".to_vec()));
+        let json = serde_json::to_string(&plan).unwrap();
+        assert!(json.contains("[47,97,47,108,255]"));
+        assert_eq!(serde_json::from_str::<Plan>(&json).unwrap(), plan);
+    }
+}
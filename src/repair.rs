@@ -0,0 +1,132 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{self, Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::exclude::Excludes;
+use crate::link::Link;
+use crate::tidy_path;
+
+/// Everything under a set of search roots that a dangling link could be
+/// pointed at instead, by basename. Symlinks aren't candidates.
+#[derive(Debug, Default)]
+pub struct Index {
+    by_name: HashMap<OsString, Vec<PathBuf>>,
+}
+
+impl Index {
+    /// Walk `roots`, skipping whatever `excludes` says to.
+    pub fn build(roots: &[PathBuf], excludes: &Excludes) -> Result<Self> {
+        let mut index = Self::default();
+        for root in roots {
+            if !root.is_dir() {
+                anyhow::bail!("{}: not a directory", root.display());
+            }
+            let root = tidy_path(&path::absolute(root)?);
+            let mut walker = WalkDir::new(&root).min_depth(1).into_iter();
+            while let Some(entry) = walker.next() {
+                let Ok(entry) = entry else {
+                    continue;
+                };
+                let is_dir = entry.file_type().is_dir();
+                if excludes.is_excluded(&root, entry.path(), is_dir) {
+                    if is_dir {
+                        walker.skip_current_dir();
+                    }
+                    continue;
+                }
+                if entry.path_is_symlink() {
+                    continue;
+                }
+                index
+                    .by_name
+                    .entry(entry.file_name().to_os_string())
+                    .or_default()
+                    .push(entry.into_path());
+            }
+        }
+        Ok(index)
+    }
+
+    /// Absolute paths with the same basename as the link's target, best first:
+    /// those sharing more trailing path components with the old target, then
+    /// more leading ones.
+    pub fn candidates(&self, link: &Link) -> Vec<PathBuf> {
+        let Ok(old) = path::absolute(&link.target_resolved).map(|p| tidy_path(&p)) else {
+            return Vec::new();
+        };
+        let Some(found) = old.file_name().and_then(|name| self.by_name.get(name)) else {
+            return Vec::new();
+        };
+        let mut ranked = found.clone();
+        ranked.sort_by_cached_key(|c| {
+            (
+                std::cmp::Reverse(shared_suffix(&old, c)),
+                std::cmp::Reverse(shared_prefix(&old, c)),
+                c.clone(),
+            )
+        });
+        ranked.dedup();
+        ranked
+    }
+
+    /// The one candidate that shares the longest suffix with the old target,
+    /// if there is exactly one such.
+    pub fn best(&self, link: &Link) -> Result<PathBuf, Vec<PathBuf>> {
+        let candidates = self.candidates(link);
+        let old = tidy_path(&path::absolute(&link.target_resolved).unwrap_or_default());
+        match candidates.as_slice() {
+            [only] => Ok(only.clone()),
+            [first, second, ..] if shared_suffix(&old, first) > shared_suffix(&old, second) => {
+                Ok(first.clone())
+            }
+            _ => Err(candidates),
+        }
+    }
+}
+
+fn shared_suffix(a: &Path, b: &Path) -> usize {
+    a.components()
+        .rev()
+        .zip(b.components().rev())
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+fn shared_prefix(a: &Path, b: &Path) -> usize {
+    a.components()
+        .zip(b.components())
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_candidates_ranked() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for file in ["new/lib/util.rs", "new/util.rs", "old/other/util.rs", "new/lib/main.rs"] {
+            fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            fs::write(root.join(file), "").unwrap();
+        }
+        let index = Index::build(&[root.to_path_buf()], &Excludes::default()).unwrap();
+        let link = Link::new(&root.join("link"), &root.join("old/lib/util.rs"));
+
+        let candidates = index.candidates(&link);
+        assert_eq!(
+            candidates,
+            vec![root.join("new/lib/util.rs"), root.join("old/other/util.rs"), root.join("new/util.rs")]
+        );
+        assert_eq!(index.best(&link), Ok(root.join("new/lib/util.rs")));
+
+        let link = Link::new(&root.join("link"), &root.join("gone/util.rs"));
+        assert_eq!(index.best(&link).unwrap_err().len(), 3);
+        let link = Link::new(&root.join("link"), &root.join("gone/nothing.rs"));
+        assert!(index.candidates(&link).is_empty());
+    }
+}
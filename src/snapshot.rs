@@ -0,0 +1,196 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::link::{Link, Status};
+
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// The symlinks under a directory, recorded so they can be made again
+/// somewhere else.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Where the links were found, for reference.
+    pub root: PathBuf,
+    pub links: Vec<Entry>,
+    /// The root as it was walked, which link origins start with.
+    #[serde(skip)]
+    walked: PathBuf,
+}
+
+/// One link of a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Where the link lives, relative to the root.
+    pub origin: PathBuf,
+    /// The target string stored in the link, exactly as read.
+    pub target: PathBuf,
+    /// Whether the target could be reached when the snapshot was taken.
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+}
+
+/// A link that differs between two snapshots, matched by origin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub origin: PathBuf,
+    /// The link as it was, if it was there.
+    pub old: Option<Entry>,
+    /// The link as it is, if it is still there.
+    pub new: Option<Entry>,
+}
+
+/// A link's own owner and permission bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Owner {
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+}
+
+impl Snapshot {
+    /// Start an empty snapshot of the links found by walking `root`.
+    pub fn new(root: &Path) -> Result<Self> {
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            root: fs::canonicalize(root).with_context(|| format!("{}: cannot snapshot", root.display()))?,
+            links: Vec::new(),
+            walked: root.to_path_buf(),
+        })
+    }
+
+    /// Record `link`, with its owner and mode if `metadata` is set. A root
+    /// that is itself a symlink is walked through, not recorded.
+    pub fn push(&mut self, link: &Link, metadata: bool) -> Result<()> {
+        let origin = link
+            .origin
+            .strip_prefix(&self.walked)
+            .with_context(|| format!("{}: not under {}", link.origin.display(), self.walked.display()))?;
+        if origin.as_os_str().is_empty() {
+            return Ok(());
+        }
+        let owner = if metadata {
+            let meta = fs::symlink_metadata(&link.origin)?;
+            Some(Owner {
+                uid: meta.uid(),
+                gid: meta.gid(),
+                mode: meta.mode() & 0o7777,
+            })
+        } else {
+            None
+        };
+        self.links.push(Entry {
+            origin: origin.to_path_buf(),
+            target: link.target.clone(),
+            status: link.status,
+            owner,
+        });
+        Ok(())
+    }
+
+    /// The links added, removed, retargeted, or that started or stopped
+    /// dangling between this snapshot and `new`, by origin.
+    pub fn diff(&self, new: &Snapshot) -> Vec<Difference> {
+        let old_links: BTreeMap<_, _> = self.links.iter().map(|entry| (&entry.origin, entry)).collect();
+        let new_links: BTreeMap<_, _> = new.links.iter().map(|entry| (&entry.origin, entry)).collect();
+        let origins: BTreeSet<_> = old_links.keys().chain(new_links.keys()).collect();
+        origins
+            .into_iter()
+            .filter_map(|origin| {
+                let (old, new) = (old_links.get(origin), new_links.get(origin));
+                if let (Some(old), Some(new)) = (old, new)
+                    && old.target == new.target
+                    && old.status == new.status
+                {
+                    return None;
+                }
+                Some(Difference {
+                    origin: origin.to_path_buf(),
+                    old: old.map(|&entry| entry.clone()),
+                    new: new.map(|&entry| entry.clone()),
+                })
+            })
+            .collect()
+    }
+
+    /// Load a snapshot from a JSON file, or from stdin if `path` is `-`.
+    /// Every origin must stay inside the directory it is restored into.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = if path == Path::new("-") {
+            io::read_to_string(io::stdin())?
+        } else {
+            fs::read_to_string(path).with_context(|| format!("{}: cannot read snapshot", path.display()))?
+        };
+        let snapshot: Snapshot =
+            serde_json::from_str(&text).with_context(|| format!("{}: invalid snapshot", path.display()))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            anyhow::bail!("{}: unsupported snapshot version {}", path.display(), snapshot.version);
+        }
+        for entry in &snapshot.links {
+            let inside = entry.origin.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+            if !inside || entry.origin.as_os_str().is_empty() {
+                anyhow::bail!("{}: origin outside the snapshot root: {}", path.display(), entry.origin.display());
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Save the snapshot as JSON, sorted by origin, or print it to stdout if
+    /// `path` is `-`.
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        self.links.sort_by(|a, b| a.origin.cmp(&b.origin));
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        if path == Path::new("-") {
+            io::stdout().write_all(json.as_bytes())?;
+        } else {
+            fs::write(path, json).with_context(|| format!("{}: cannot write snapshot", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink("../missing", dir.path().join("b")).unwrap();
+        std::os::unix::fs::symlink(".", dir.path().join("a")).unwrap();
+
+        let mut snapshot = Snapshot::new(dir.path()).unwrap();
+        for name in ["b", "a"] {
+            let link = Link::read(&dir.path().join(name)).unwrap();
+            snapshot.push(&link, true).unwrap();
+        }
+        let file = dir.path().join("snapshot.json");
+        snapshot.save(&file).unwrap();
+
+        let loaded = Snapshot::load(&file).unwrap();
+        let origins: Vec<_> = loaded.links.iter().map(|e| e.origin.clone()).collect();
+        assert_eq!(origins, [PathBuf::from("a"), PathBuf::from("b")]);
+        assert_eq!(loaded.links[1].status, Status::Dangling);
+        assert!(loaded.links[0].owner.is_some());
+
+        let mut changed = loaded.clone();
+        changed.links[0].target = PathBuf::from("..");
+        changed.links.remove(1);
+        let differences = loaded.diff(&changed);
+        assert_eq!(differences.len(), 2);
+        assert_eq!(differences[0].new.as_ref().unwrap().target, PathBuf::from(".."));
+        assert!(differences[1].new.is_none());
+        assert!(loaded.diff(&loaded).is_empty());
+
+        let json = serde_json::to_string(&loaded).unwrap().replace("\"b\"", "\"../b\"");
+        fs::write(&file, json).unwrap();
+        assert!(Snapshot::load(&file).is_err());
+    }
+}
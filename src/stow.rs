@@ -0,0 +1,385 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use crate::exclude::Excludes;
+use crate::tidy_path;
+
+/// One step of stowing or unstowing a package, to be made in order.
+#[derive(Debug, Clone, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Op {
+    /// Create a symlink.
+    Link { path: PathBuf, target: PathBuf },
+    /// Remove a symlink.
+    Unlink { path: PathBuf, target: PathBuf },
+    /// Create a directory where a folded link was, so more than one package
+    /// can put links in it.
+    Mkdir { path: PathBuf },
+    /// Remove a directory whose links are being folded back into one.
+    Rmdir { path: PathBuf },
+}
+
+impl Op {
+    pub fn path(&self) -> &Path {
+        match self {
+            Op::Link { path, .. } | Op::Unlink { path, .. } | Op::Mkdir { path } | Op::Rmdir { path } => path,
+        }
+    }
+
+    pub fn apply(&self) -> Result<()> {
+        match self {
+            Op::Link { path, target } => symlink(target, path),
+            Op::Unlink { path, .. } => fs::remove_file(path),
+            Op::Mkdir { path } => fs::create_dir(path),
+            Op::Rmdir { path } => fs::remove_dir(path),
+        }
+        .with_context(|| format!("{}: cannot {}", self.path().display(), self))
+    }
+}
+
+/// Something in the target directory that stowing a package would clobber.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub path: PathBuf,
+    pub reason: &'static str,
+}
+
+/// What a path in the target directory is, or will be once the ops planned
+/// so far are made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Absent,
+    Dir,
+    File,
+    Link(PathBuf),
+}
+
+/// Plans stowing and unstowing packages, GNU Stow style: each entry of a
+/// package directory is linked into the target directory with a relative
+/// symlink, a whole directory at a time where nothing is there yet.
+///
+/// Links pointing into the directory holding a package (its stow directory)
+/// are taken to have been made by stow, so they may be removed, or split
+/// into a directory of links when another package needs the same directory.
+/// Anything else in the way is a conflict.
+#[derive(Debug, Default)]
+pub struct Stow {
+    excludes: Excludes,
+    /// What the ops planned so far do to the target tree, by path. Everything
+    /// under a path in here is in here too.
+    planned: BTreeMap<PathBuf, Node>,
+    ops: Vec<Op>,
+    conflicts: Vec<Conflict>,
+    unchanged: usize,
+}
+
+/// Where a package is stowed by default: the parent of its stow directory.
+pub fn default_target(package: &Path) -> Result<PathBuf> {
+    let package = fs::canonicalize(package).with_context(|| format!("{}: cannot stow", package.display()))?;
+    package
+        .parent()
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .with_context(|| format!("{}: no default target directory, use --into", package.display()))
+}
+
+impl Stow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leave out package entries matching `excludes`, relative to the package.
+    pub fn excludes(mut self, excludes: Excludes) -> Self {
+        self.excludes = excludes;
+        self
+    }
+
+    /// The ops planned so far.
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// What stood in the way. If there is anything here, no ops should be made.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// How many package entries were already stowed.
+    pub fn unchanged(&self) -> usize {
+        self.unchanged
+    }
+
+    /// Plan linking everything in `package` into `into`.
+    pub fn stow(&mut self, package: &Path, into: &Path) -> Result<()> {
+        let (package, into) = locate(package, into)?;
+        self.stow_dir(&package, &package, &into)
+    }
+
+    /// Plan removing every link into `package` from `into`, folding
+    /// directories that are left with links into just one other package.
+    pub fn unstow(&mut self, package: &Path, into: &Path) -> Result<()> {
+        let (package, into) = locate(package, into)?;
+        self.unstow_dir(&package, &package, &into)
+    }
+
+    fn stow_dir(&mut self, package: &Path, src_dir: &Path, dst_dir: &Path) -> Result<()> {
+        let stow_dir = package.parent().unwrap_or(package);
+        for (name, src) in entries(src_dir)? {
+            let is_dir = fs::symlink_metadata(&src)?.is_dir();
+            if self.excludes.is_excluded(package, &src, is_dir) {
+                continue;
+            }
+            let dst = dst_dir.join(&name);
+            match self.node(&dst)? {
+                Node::Absent => self.link(&dst, &src),
+                Node::Link(target) => {
+                    let current = resolve(&dst, &target);
+                    if current == src {
+                        self.unchanged += 1;
+                    } else if is_dir && current.starts_with(stow_dir) && current.is_dir() {
+                        self.unfold(&dst, &target, &current)?;
+                        self.stow_dir(package, &src, &dst)?;
+                    } else {
+                        self.conflict(&dst, "existing symlink is in the way");
+                    }
+                }
+                Node::Dir if is_dir => self.stow_dir(package, &src, &dst)?,
+                Node::Dir => self.conflict(&dst, "existing directory is in the way"),
+                Node::File => self.conflict(&dst, "existing file is in the way"),
+            }
+        }
+        Ok(())
+    }
+
+    fn unstow_dir(&mut self, package: &Path, src_dir: &Path, dst_dir: &Path) -> Result<()> {
+        for (name, node) in self.children(dst_dir)? {
+            let dst = dst_dir.join(&name);
+            let src = src_dir.join(&name);
+            match node {
+                // including links to whatever has since gone from the package
+                Node::Link(target) if resolve(&dst, &target).starts_with(package) => {
+                    self.unlink(&dst, &target);
+                }
+                Node::Dir if fs::symlink_metadata(&src).is_ok_and(|m| m.is_dir()) => {
+                    let planned = self.ops.len();
+                    self.unstow_dir(package, &src, &dst)?;
+                    if self.ops.len() > planned {
+                        self.refold(package.parent().unwrap_or(package), &dst)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the link `dst` to directory `current` with a directory of
+    /// links to each of its entries.
+    fn unfold(&mut self, dst: &Path, target: &Path, current: &Path) -> Result<()> {
+        self.unlink(dst, target);
+        self.ops.push(Op::Mkdir { path: dst.to_path_buf() });
+        self.planned.insert(dst.to_path_buf(), Node::Dir);
+        for (name, src) in entries(current)? {
+            self.link(&dst.join(name), &src);
+        }
+        Ok(())
+    }
+
+    /// Replace directory `dir` with a single link if it holds nothing but
+    /// links to every entry of one directory under `stow_dir`.
+    fn refold(&mut self, stow_dir: &Path, dir: &Path) -> Result<()> {
+        let children = self.children(dir)?;
+        let mut source = None;
+        for (name, node) in &children {
+            let Node::Link(target) = node else {
+                return Ok(());
+            };
+            let current = resolve(&dir.join(name), target);
+            let Some(parent) = current.parent().filter(|_| current.file_name() == Some(name.as_os_str())) else {
+                return Ok(());
+            };
+            if source.as_deref().is_some_and(|s| s != parent) {
+                return Ok(());
+            }
+            source = Some(parent.to_path_buf());
+        }
+        let Some(source) = source else {
+            return Ok(());
+        };
+        if !source.starts_with(stow_dir) || !source.is_dir() {
+            return Ok(());
+        }
+        let names: Vec<_> = entries(&source)?.into_iter().map(|(name, _)| name).collect();
+        if names.iter().ne(children.iter().map(|(name, _)| name)) {
+            return Ok(());
+        }
+        for (name, node) in children {
+            if let Node::Link(target) = node {
+                self.unlink(&dir.join(name), &target);
+            }
+        }
+        self.ops.push(Op::Rmdir { path: dir.to_path_buf() });
+        self.planned.insert(dir.to_path_buf(), Node::Absent);
+        self.link(dir, &source);
+        Ok(())
+    }
+
+    fn link(&mut self, dst: &Path, src: &Path) {
+        let dir = dst.parent().unwrap_or(Path::new("/"));
+        let target = pathdiff::diff_paths(src, dir).unwrap_or_else(|| src.to_path_buf());
+        self.planned.insert(dst.to_path_buf(), Node::Link(target.clone()));
+        self.ops.push(Op::Link {
+            path: dst.to_path_buf(),
+            target,
+        });
+    }
+
+    fn unlink(&mut self, dst: &Path, target: &Path) {
+        self.planned.insert(dst.to_path_buf(), Node::Absent);
+        self.ops.push(Op::Unlink {
+            path: dst.to_path_buf(),
+            target: target.to_path_buf(),
+        });
+    }
+
+    fn conflict(&mut self, path: &Path, reason: &'static str) {
+        self.conflicts.push(Conflict {
+            path: path.to_path_buf(),
+            reason,
+        });
+    }
+
+    fn node(&self, path: &Path) -> Result<Node> {
+        if let Some(node) = self.planned.get(path) {
+            return Ok(node.clone());
+        }
+        if path.ancestors().skip(1).any(|dir| self.planned.contains_key(dir)) {
+            return Ok(Node::Absent);
+        }
+        match fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_symlink() => Ok(Node::Link(fs::read_link(path)?)),
+            Ok(meta) if meta.is_dir() => Ok(Node::Dir),
+            Ok(_) => Ok(Node::File),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Node::Absent),
+            Err(e) => Err(e).with_context(|| format!("{}: cannot stat", path.display())),
+        }
+    }
+
+    /// The entries of `dir` as planned, by name.
+    fn children(&self, dir: &Path) -> Result<Vec<(OsString, Node)>> {
+        let mut names = BTreeSet::new();
+        if !dir.ancestors().any(|d| self.planned.contains_key(d)) {
+            for entry in fs::read_dir(dir).with_context(|| format!("{}: cannot read", dir.display()))? {
+                names.insert(entry?.file_name());
+            }
+        }
+        let planned = self.planned.range(dir.to_path_buf()..).take_while(|(path, _)| path.starts_with(dir));
+        names.extend(
+            planned
+                .filter(|(path, _)| path.parent() == Some(dir))
+                .filter_map(|(path, _)| path.file_name().map(OsString::from)),
+        );
+        let mut children = Vec::new();
+        for name in names {
+            match self.node(&dir.join(&name))? {
+                Node::Absent => {}
+                node => children.push((name, node)),
+            }
+        }
+        Ok(children)
+    }
+}
+
+/// Canonicalize a package and target directory, checking they can be used.
+fn locate(package: &Path, into: &Path) -> Result<(PathBuf, PathBuf)> {
+    let package = fs::canonicalize(package).with_context(|| format!("{}: cannot stow", package.display()))?;
+    let into = fs::canonicalize(into).with_context(|| format!("{}: cannot stow into", into.display()))?;
+    for dir in [&package, &into] {
+        if !dir.is_dir() {
+            anyhow::bail!("{}: not a directory", dir.display());
+        }
+    }
+    if into.starts_with(&package) {
+        anyhow::bail!("{}: target directory is inside the package", into.display());
+    }
+    Ok((package, into))
+}
+
+/// Where the link at `path` to `target` points, lexically.
+fn resolve(path: &Path, target: &Path) -> PathBuf {
+    tidy_path(&path.parent().unwrap_or(Path::new("/")).join(target))
+}
+
+/// The entries of `dir`, sorted by name.
+fn entries(dir: &Path) -> Result<Vec<(OsString, PathBuf)>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("{}: cannot read", dir.display()))? {
+        let entry = entry?;
+        entries.push((entry.file_name(), entry.path()));
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unfold_and_refold() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        for file in ["stow/vim/.config/vim/vimrc", "stow/git/.config/git/config"] {
+            fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            fs::write(root.join(file), "").unwrap();
+        }
+        fs::create_dir(root.join("home")).unwrap();
+        let home = root.join("home");
+
+        let mut stow = Stow::new();
+        stow.stow(&root.join("stow/vim"), &home).unwrap();
+        stow.stow(&root.join("stow/git"), &home).unwrap();
+        let config = home.join(".config");
+        assert_eq!(
+            stow.ops(),
+            [
+                Op::Link {
+                    path: config.clone(),
+                    target: "../stow/vim/.config".into()
+                },
+                Op::Unlink {
+                    path: config.clone(),
+                    target: "../stow/vim/.config".into()
+                },
+                Op::Mkdir { path: config.clone() },
+                Op::Link {
+                    path: config.join("vim"),
+                    target: "../../stow/vim/.config/vim".into()
+                },
+                Op::Link {
+                    path: config.join("git"),
+                    target: "../../stow/git/.config/git".into()
+                },
+            ]
+        );
+
+        for op in stow.ops() {
+            op.apply().unwrap();
+        }
+        let mut stow = Stow::new();
+        stow.unstow(&root.join("stow/git"), &home).unwrap();
+        assert_eq!(
+            stow.ops().last(),
+            Some(&Op::Link {
+                path: config.clone(),
+                target: "../stow/vim/.config".into()
+            })
+        );
+        assert!(stow.conflicts().is_empty());
+    }
+}
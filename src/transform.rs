@@ -0,0 +1,401 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{self, Path, PathBuf};
+
+use crate::link::{Chain, Link, Status};
+use crate::repair::Index;
+use crate::{
+    create_hard_link_tree, create_symlink_tree, replace_symlink, replace_with_hard_link, tidy_path,
+};
+
+/// A modification to be made to a single symlink.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, strum::Display)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Change {
+    /// Point the link at a new target string.
+    Retarget {
+        #[serde(with = "crate::plan::os_path")]
+        target: PathBuf,
+    },
+    /// Delete the link.
+    Remove,
+    /// Replace the link with a hardlink to its target.
+    ReplaceWithHardlink {
+        #[serde(with = "crate::plan::os_path")]
+        target: PathBuf,
+    },
+    /// Replace the link with a directory tree mirroring its target.
+    ExpandToTree {
+        #[serde(with = "crate::plan::os_path")]
+        target: PathBuf,
+        hard: bool,
+    },
+    /// Move the target to the link's location.
+    MoveTarget {
+        #[serde(with = "crate::plan::os_path")]
+        target: PathBuf,
+    },
+}
+
+impl Change {
+    /// Carry out the change on the link at `origin`.
+    pub fn apply(&self, origin: &Path) -> Result<()> {
+        match self {
+            Change::Retarget { target } => {
+                replace_symlink(target, origin)?;
+            }
+            Change::Remove => {
+                fs::remove_file(origin)?;
+            }
+            Change::ReplaceWithHardlink { target } => {
+                replace_with_hard_link(target, origin)?;
+            }
+            Change::ExpandToTree { target, hard } => {
+                fs::remove_file(origin)?;
+                if *hard {
+                    create_hard_link_tree(target, origin)?;
+                } else {
+                    create_symlink_tree(target, origin)?;
+                }
+            }
+            Change::MoveTarget { target } => {
+                let meta = fs::symlink_metadata(target)
+                    .with_context(|| format!("{}: target {} is gone", origin.display(), target.display()))?;
+                // a file is renamed over the link atomically, but a directory
+                // can't replace a symlink, so the link has to go first
+                if meta.is_dir() {
+                    fs::remove_file(origin)?;
+                }
+                fs::rename(target, origin)
+                    .with_context(|| format!("{}: cannot move {} here", origin.display(), target.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why a link was passed over by a transformation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    Dangling,
+    AlreadyTidy,
+    IdenticalTarget,
+    Directory,
+    File,
+    CrossDevice,
+    AlreadyFlat,
+    BrokenChain,
+    WouldDangle,
+    NoCandidate,
+    Ambiguous,
+    Declined,
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Skip::Dangling => "skipping dangling symlink",
+            Skip::AlreadyTidy => "target is already tidy",
+            Skip::IdenticalTarget => "new target is identical to old target",
+            Skip::Directory => "skipping directory",
+            Skip::File => "skipping file",
+            Skip::CrossDevice => "target is on another filesystem",
+            Skip::AlreadyFlat => "target is not a symlink",
+            Skip::BrokenChain => "chain of symlinks does not resolve",
+            Skip::WouldDangle => "new target does not exist",
+            Skip::NoCandidate => "no candidate target found",
+            Skip::Ambiguous => "several candidate targets, use --choose to pick one",
+            Skip::Declined => "no candidate chosen",
+        };
+        f.write_str(msg)
+    }
+}
+
+/// The result of planning a transformation on a link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Change(Change),
+    Skip(Skip),
+    /// The transformation doesn't apply to this link.
+    Unchanged,
+}
+
+/// Make an absolute link relative. Without `canonicalize`, the new target is
+/// worked out lexically, so it still goes through any symlinks the old one did.
+/// Given `within`, links whose target lies outside that directory are made
+/// absolute instead, so the tree can be moved without breaking them.
+pub fn to_relative(link: &Link, canonicalize: bool, within: Option<&Path>) -> Result<Outcome> {
+    if link.is_dangling {
+        return Ok(Outcome::Skip(Skip::Dangling));
+    }
+    if let Some(dir) = within
+        && !absolute_target(link, canonicalize)?.starts_with(absolute_path(dir, canonicalize)?)
+    {
+        return to_absolute(link, canonicalize);
+    }
+    if !link.is_absolute {
+        return Ok(Outcome::Unchanged);
+    }
+    // Resolve the target and the link's parent to find the relative difference
+    let abs_target = absolute_target(link, canonicalize)?;
+    let abs_link_dir = absolute_path(link.dir(), canonicalize)?;
+    Ok(match pathdiff::diff_paths(&abs_target, &abs_link_dir) {
+        Some(target) => Outcome::Change(Change::Retarget { target }),
+        None => Outcome::Unchanged,
+    })
+}
+
+/// Make a relative link absolute. Without `canonicalize`, the new target is
+/// worked out lexically, so it still goes through any symlinks the old one did.
+pub fn to_absolute(link: &Link, canonicalize: bool) -> Result<Outcome> {
+    if link.is_dangling {
+        return Ok(Outcome::Skip(Skip::Dangling));
+    }
+    if link.is_absolute {
+        return Ok(Outcome::Unchanged);
+    }
+    let target = absolute_target(link, canonicalize).context(format!(
+        "Failed to resolve absolute path for {}",
+        link.origin.display()
+    ))?;
+    Ok(Outcome::Change(Change::Retarget { target }))
+}
+
+fn absolute_target(link: &Link, canonicalize: bool) -> Result<PathBuf> {
+    absolute_path(&link.target_resolved, canonicalize)
+}
+
+/// Either canonicalize `path` or, lexically, make it absolute and tidy.
+fn absolute_path(path: &Path, canonicalize: bool) -> Result<PathBuf> {
+    Ok(if canonicalize {
+        fs::canonicalize(path)?
+    } else {
+        tidy_path(&path::absolute(path)?)
+    })
+}
+
+pub fn tidy(link: &Link) -> Result<Outcome> {
+    let target = tidy_path(&link.target);
+    if target.as_os_str() == link.target.as_os_str() {
+        return Ok(Outcome::Skip(Skip::AlreadyTidy));
+    }
+    Ok(Outcome::Change(Change::Retarget { target }))
+}
+
+pub fn edit_target(link: &Link, pattern: &Regex, replace: &str, replace_all: bool) -> Result<Outcome> {
+    let target_str = link.target_str();
+    if !pattern.is_match(&target_str) {
+        return Ok(Outcome::Unchanged);
+    }
+    let new_target_str = if replace_all {
+        pattern.replace_all(&target_str, replace).into_owned()
+    } else {
+        pattern.replace(&target_str, replace).into_owned()
+    };
+    if new_target_str == target_str {
+        return Ok(Outcome::Skip(Skip::IdenticalTarget));
+    }
+    Ok(Outcome::Change(Change::Retarget {
+        target: PathBuf::from(new_target_str),
+    }))
+}
+
+/// Point the link past the other symlinks it leads to: all of them, or at most
+/// `collapse`. The new target is relative if the old one was.
+pub fn flatten(link: &Link, max_hops: usize, collapse: Option<usize>) -> Result<Outcome> {
+    if link.is_dangling {
+        return Ok(Outcome::Skip(Skip::Dangling));
+    }
+    let chain = Chain::follow(&link.origin, &link.target, max_hops);
+    if chain.status != Status::Attached {
+        return Ok(Outcome::Skip(Skip::BrokenChain));
+    }
+    if chain.hops.len() == 1 {
+        return Ok(Outcome::Skip(Skip::AlreadyFlat));
+    }
+    let skip = collapse.unwrap_or(usize::MAX).min(chain.hops.len() - 1);
+    if skip == 0 {
+        return Ok(Outcome::Unchanged);
+    }
+
+    // canonicalize the new target's directory, but not the target itself,
+    // which may be a symlink we were told to stop at
+    let new_target = &chain.hops[skip].resolved;
+    let name = new_target
+        .file_name()
+        .with_context(|| format!("{}: cannot flatten onto a path ending in ..", link.origin.display()))?;
+    let dir = new_target.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let abs_target = fs::canonicalize(dir)?.join(name);
+    let target = if link.is_absolute {
+        abs_target
+    } else {
+        let abs_link_dir = fs::canonicalize(link.dir())?;
+        match pathdiff::diff_paths(&abs_target, &abs_link_dir) {
+            Some(target) => target,
+            None => return Ok(Outcome::Unchanged),
+        }
+    };
+    if target == link.target {
+        return Ok(Outcome::Skip(Skip::IdenticalTarget));
+    }
+    Ok(Outcome::Change(Change::Retarget { target }))
+}
+
+/// Move a target under `from` to the same place under `to`, matching whole
+/// path components. Relative targets are resolved lexically and stay relative.
+/// If `existing_only` is set, links whose new target is missing are skipped.
+pub fn relocate(link: &Link, from: &Path, to: &Path, existing_only: bool) -> Result<Outcome> {
+    let from = tidy_path(&path::absolute(from)?);
+    let to = tidy_path(&path::absolute(to)?);
+    let Ok(rest) = absolute_path(&link.target_resolved, false)?.strip_prefix(&from).map(Path::to_path_buf)
+    else {
+        return Ok(Outcome::Unchanged);
+    };
+    // a link to `from` itself moves to `to`, not to `to/`
+    let new_target = if rest.as_os_str().is_empty() { to } else { to.join(rest) };
+    let outcome = retarget(link, &new_target)?;
+    if let Outcome::Change(Change::Retarget { target }) = &outcome
+        && existing_only
+        && Link::new(&link.origin, target).is_dangling
+    {
+        return Ok(Outcome::Skip(Skip::WouldDangle));
+    }
+    Ok(outcome)
+}
+
+/// Point a dangling link at the one candidate in `index` that stands out:
+/// the only file with its target's name, or the only one sharing the most
+/// trailing path components with it. Relative targets stay relative.
+pub fn repair(link: &Link, index: &Index) -> Result<Outcome> {
+    if !link.is_dangling {
+        return Ok(Outcome::Unchanged);
+    }
+    match index.best(link) {
+        Ok(found) => retarget(link, &found),
+        Err(candidates) if candidates.is_empty() => Ok(Outcome::Skip(Skip::NoCandidate)),
+        Err(_) => Ok(Outcome::Skip(Skip::Ambiguous)),
+    }
+}
+
+/// Point the link at `abs_target`, given relative to the link's directory if
+/// the old target was relative. Paths are compared lexically.
+pub fn retarget(link: &Link, abs_target: &Path) -> Result<Outcome> {
+    let target = if link.is_absolute {
+        abs_target.to_path_buf()
+    } else {
+        match pathdiff::diff_paths(abs_target, absolute_path(link.dir(), false)?) {
+            Some(target) => target,
+            None => return Ok(Outcome::Unchanged),
+        }
+    };
+    if target == link.target {
+        return Ok(Outcome::Skip(Skip::IdenticalTarget));
+    }
+    Ok(Outcome::Change(Change::Retarget { target }))
+}
+
+pub fn to_hardlink(link: &Link) -> Result<Outcome> {
+    if link.is_dangling {
+        return Ok(Outcome::Skip(Skip::Dangling));
+    }
+    if link.target_resolved.is_dir() {
+        return Ok(Outcome::Skip(Skip::Directory));
+    }
+    if let Some(dev) = link.root_dev
+        && fs::metadata(&link.target_resolved).is_ok_and(|m| m.dev() != dev)
+    {
+        return Ok(Outcome::Skip(Skip::CrossDevice));
+    }
+    Ok(Outcome::Change(Change::ReplaceWithHardlink {
+        target: link.target_resolved.clone(),
+    }))
+}
+
+pub fn to_tree(link: &Link, hard: bool) -> Result<Outcome> {
+    if link.is_dangling {
+        return Ok(Outcome::Skip(Skip::Dangling));
+    }
+    if !link.target_resolved.is_dir() {
+        return Ok(Outcome::Skip(Skip::File));
+    }
+    Ok(Outcome::Change(Change::ExpandToTree {
+        target: link.target_resolved.clone(),
+        hard,
+    }))
+}
+
+pub fn replace_with_target(link: &Link) -> Result<Outcome> {
+    if link.is_dangling {
+        return Ok(Outcome::Skip(Skip::Dangling));
+    }
+    Ok(Outcome::Change(Change::MoveTarget {
+        target: fs::canonicalize(&link.target_resolved)?,
+    }))
+}
+
+pub fn remove(_link: &Link) -> Result<Outcome> {
+    Ok(Outcome::Change(Change::Remove))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn temp_link(target: &str) -> (tempfile::TempDir, Link) {
+        let dir = tempfile::tempdir().unwrap();
+        let origin = dir.path().join("link");
+        symlink(target, &origin).unwrap();
+        let link = Link::read(&origin).unwrap();
+        (dir, link)
+    }
+
+    #[test]
+    fn test_tidy_plans_retarget() {
+        let (_dir, link) = temp_link("foo/../bar");
+        assert_eq!(
+            tidy(&link).unwrap(),
+            Outcome::Change(Change::Retarget {
+                target: PathBuf::from("bar")
+            })
+        );
+    }
+
+    #[test]
+    fn test_tidy_already_tidy() {
+        let (_dir, link) = temp_link("bar");
+        assert_eq!(tidy(&link).unwrap(), Outcome::Skip(Skip::AlreadyTidy));
+    }
+
+    #[test]
+    fn test_dangling_skipped() {
+        let (_dir, link) = temp_link("missing");
+        assert_eq!(to_absolute(&link, true).unwrap(), Outcome::Skip(Skip::Dangling));
+        assert_eq!(to_hardlink(&link).unwrap(), Outcome::Skip(Skip::Dangling));
+    }
+
+    #[test]
+    fn test_to_hardlink_cross_device() {
+        let (dir, link) = temp_link("file");
+        fs::write(dir.path().join("file"), "").unwrap();
+        let mut link = Link::read(&link.origin).unwrap();
+        let dev = fs::metadata(dir.path()).unwrap().dev();
+
+        link.root_dev = Some(dev);
+        assert!(matches!(to_hardlink(&link).unwrap(), Outcome::Change(_)));
+        link.root_dev = Some(dev + 1);
+        assert_eq!(to_hardlink(&link).unwrap(), Outcome::Skip(Skip::CrossDevice));
+    }
+
+    #[test]
+    fn test_edit_target_no_match() {
+        let (_dir, link) = temp_link("bar");
+        let re = Regex::new("baz").unwrap();
+        assert_eq!(edit_target(&link, &re, "qux", false).unwrap(), Outcome::Unchanged);
+    }
+}
@@ -8,12 +8,14 @@ use tempfile::{tempdir, TempDir};
 
 pub struct TestContext {
     temp_dir: TempDir,
+    state_dir: TempDir,
 }
 
 impl TestContext {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
         Ok(Self {
             temp_dir: tempdir()?,
+            state_dir: tempdir()?,
         })
     }
 
@@ -21,10 +23,16 @@ impl TestContext {
         self.temp_dir.path()
     }
 
+    #[allow(dead_code)]
+    pub fn state_path(&self) -> &Path {
+        self.state_dir.path()
+    }
+
     #[allow(dead_code)]
     pub fn slinky_cmd(&self) -> Command {
         let mut cmd = Command::new(assert_cmd::cargo_bin!("slinky"));
         cmd.current_dir(self.path());
+        cmd.env("SLINKY_STATE_DIR", self.state_path());
         cmd
     }
 
@@ -42,6 +50,14 @@ impl TestContext {
         cmd.assert()
     }
 
+    #[allow(dead_code)]
+    pub fn run_slinky_with_stdin(&self, args: &[&str], stdin: &str) -> Assert {
+        let mut cmd = assert_cmd::Command::from_std(self.slinky_cmd());
+        cmd.args(args);
+        cmd.write_stdin(stdin);
+        cmd.assert()
+    }
+
     #[allow(dead_code)]
     pub fn run_slinky_ln(&self, args: &[&str]) -> Assert {
         let mut cmd = self.slinky_ln_cmd();
@@ -49,6 +65,15 @@ impl TestContext {
         cmd.assert()
     }
 
+    #[allow(dead_code)]
+    pub fn run_slinky_ln_with_stdin(&self, args: &[&str], stdin: &str) -> Assert {
+        let mut cmd = assert_cmd::Command::from_std(self.slinky_ln_cmd());
+        cmd.args(args);
+        cmd.write_stdin(stdin);
+        cmd.assert()
+    }
+
+    #[allow(dead_code)]
     pub fn create_file(&self, name: &str, content: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
         let file_path = self.path().join(name);
         if let Some(parent) = file_path.parent() {
@@ -0,0 +1,63 @@
+use predicates::prelude::*;
+use std::fs;
+
+mod common;
+use common::TestContext;
+
+fn create_trees(ctx: &TestContext) -> Result<(), Box<dyn std::error::Error>> {
+    ctx.create_file("old/lib/libfoo.so.1", "")?;
+    ctx.create_symlink("libfoo.so.1", "old/lib/libfoo.so")?;
+    ctx.create_symlink("libbar.so.1", "old/lib/libbar.so")?;
+    ctx.create_symlink("../lib", "old/bin/lib")?;
+    ctx.create_symlink("lib", "old/gone")?;
+
+    ctx.create_file("new/lib/libfoo.so.2", "")?;
+    ctx.create_file("new/lib/libbar.so.1", "")?;
+    ctx.create_symlink("libfoo.so.2", "new/lib/libfoo.so")?;
+    ctx.create_symlink("libbar.so.1", "new/lib/libbar.so")?;
+    ctx.create_symlink("../lib", "new/bin/lib")?;
+    ctx.create_symlink("lib/libfoo.so", "new/added")?;
+    Ok(())
+}
+
+#[test]
+fn test_diff_trees() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_trees(&ctx)?;
+
+    ctx.run_slinky(&["diff", "old", "new"])
+        .success()
+        .stdout(predicate::str::contains("+ added -> lib/libfoo.so"))
+        .stdout(predicate::str::contains("- gone -> lib"))
+        .stdout(predicate::str::contains("~ lib/libfoo.so -> (libfoo.so.1 => libfoo.so.2)"))
+        .stdout(predicate::str::contains("! lib/libbar.so -> libbar.so.1 (dangling => attached)"))
+        .stdout(predicate::str::contains("bin/lib").not())
+        .stderr(predicate::str::contains("diff: 1 added, 1 removed, 1 retargeted, 1 changed status"));
+
+    ctx.run_slinky(&["diff", "--exit-code", "old", "new"]).code(1);
+    ctx.run_slinky(&["diff", "--exit-code", "new", "new"])
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_diff_snapshot() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_trees(&ctx)?;
+    ctx.run_slinky(&["old", "snapshot", "old.json"]).success();
+
+    // a snapshot compares like the tree it was taken of
+    fs::remove_dir_all(ctx.path().join("old"))?;
+    ctx.run_slinky(&["diff", "old.json", "new"])
+        .success()
+        .stdout(predicate::str::contains("~ lib/libfoo.so -> (libfoo.so.1 => libfoo.so.2)"))
+        .stderr(predicate::str::contains("diff: 1 added, 1 removed, 1 retargeted, 1 changed status"));
+
+    ctx.run_slinky(&["diff", "new", "missing.json"])
+        .code(2)
+        .stderr(predicate::str::contains("missing.json: cannot read snapshot"));
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use predicates::prelude::*;
+
+mod common;
+use common::TestContext;
+
+fn create_tree(ctx: &TestContext) -> Result<(), Box<dyn std::error::Error>> {
+    ctx.create_symlink("target", "src/link")?;
+    ctx.create_symlink("target", "src/old.bak")?;
+    ctx.create_symlink("target", "node_modules/pkg/link")?;
+    ctx.create_symlink("target", "target/debug/link")?;
+    Ok(())
+}
+
+#[test]
+fn test_exclude() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_tree(&ctx)?;
+
+    for jobs in ["1", "4"] {
+        ctx.run_slinky(&[
+            "-j",
+            jobs,
+            "--exclude",
+            "node_modules",
+            "--exclude",
+            "*.bak",
+            "list",
+            "--origin-only",
+        ])
+        .success()
+        .stdout(predicate::str::contains("./src/link"))
+        .stdout(predicate::str::contains("./target/debug/link"))
+        .stdout(predicate::str::contains("node_modules").not())
+        .stdout(predicate::str::contains("old.bak").not());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_include_overrides_exclude() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_tree(&ctx)?;
+
+    ctx.run_slinky(&["--exclude", "*.bak", "--include", "old.bak", "src", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::diff("src/link\nsrc/old.bak\n").or(predicate::str::diff("src/old.bak\nsrc/link\n")));
+
+    Ok(())
+}
+
+#[test]
+fn test_exclude_prunes_directories() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_tree(&ctx)?;
+
+    // node_modules is never descended into, so nothing under it can be included again
+    for jobs in ["1", "4"] {
+        ctx.run_slinky(&[
+            "-j",
+            jobs,
+            "--exclude",
+            "node_modules/",
+            "--include",
+            "node_modules/pkg/link",
+            "list",
+            "--origin-only",
+        ])
+        .success()
+        .stdout(predicate::str::contains("node_modules").not());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_exclude_from() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_tree(&ctx)?;
+    let patterns = ctx.create_file("patterns", "# build output\n/target\nnode_modules/\n")?;
+
+    ctx.run_slinky(&["--exclude-from", patterns.to_str().unwrap(), "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::contains("./src/link"))
+        .stdout(predicate::str::contains("target/debug").not())
+        .stdout(predicate::str::contains("node_modules").not());
+
+    ctx.run_slinky(&["--exclude-from", "missing", "list"])
+        .code(2)
+        .stderr(predicate::str::contains("missing: cannot read exclude file"));
+
+    Ok(())
+}
+
+#[test]
+fn test_gitignore() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_tree(&ctx)?;
+    ctx.create_file(".gitignore", "node_modules/\ntarget/\n")?;
+    ctx.create_file("src/.ignore", "*.bak\n")?;
+
+    ctx.run_slinky(&["list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::contains("node_modules"));
+
+    for jobs in ["1", "4"] {
+        ctx.run_slinky(&["-j", jobs, "--gitignore", "list", "--origin-only"])
+            .success()
+            .stdout(predicate::str::diff("./src/link\n"));
+    }
+
+    // patterns on the command line win over ignore files
+    ctx.run_slinky(&["--gitignore", "--include", "target", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::contains("./target/debug/link"));
+
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use predicates::prelude::*;
+
+mod common;
+use common::TestContext;
+
+#[test]
+fn test_list_json() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("real.txt", "content")?;
+    ctx.create_symlink("real.txt", "sub/link.txt")?;
+    ctx.create_symlink("/nonexistent/target", "broken.txt")?;
+
+    let output = ctx.run_slinky(&["--format", "json", "list"])
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let records: serde_json::Value = serde_json::from_slice(&output)?;
+    let records = records.as_array().unwrap();
+    assert_eq!(records.len(), 2);
+
+    let broken = records.iter().find(|r| r["origin"] == "./broken.txt").unwrap();
+    assert_eq!(broken["target"], "/nonexistent/target");
+    assert_eq!(broken["dangling"], true);
+    assert_eq!(broken["absolute"], true);
+    assert_eq!(broken["depth"], 1);
+    assert_eq!(broken["action"], "list");
+
+    let link = records.iter().find(|r| r["origin"] == "./sub/link.txt").unwrap();
+    assert_eq!(link["target_resolved"], "./sub/real.txt");
+    assert_eq!(link["depth"], 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_list_ndjson() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("a", "link1")?;
+    ctx.create_symlink("b", "link2")?;
+
+    let output = ctx.run_slinky(&["--format", "ndjson", "list"])
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let lines = String::from_utf8(output)?;
+    assert_eq!(lines.lines().count(), 2);
+    for line in lines.lines() {
+        let record: serde_json::Value = serde_json::from_str(line)?;
+        assert_eq!(record["dangling"], true);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_list_tsv_escapes() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("tab\there", "link")?;
+
+    ctx.run_slinky(&["--format", "tsv", "list"])
+        .success()
+        .stdout(predicate::str::diff("./link\ttab\\there\t./tab\\there\ttrue\tfalse\t1\tlist\t\tdangling\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_list_null() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("line\nbreak", "link")?;
+
+    ctx.run_slinky(&["--format", "null", "list"])
+        .success()
+        .stdout(predicate::str::diff("./link\0line\nbreak\0./line\nbreak\0true\0false\x001\0list\0\0dangling\0"));
+
+    Ok(())
+}
+
+#[test]
+fn test_verbose_ndjson_change() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("foo/../bar", "link.txt")?;
+
+    let output = ctx.run_slinky(&["-v", "--format", "ndjson", "tidy"])
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let record: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(record["action"], "retarget");
+    assert_eq!(record["target"], "foo/../bar");
+    assert_eq!(record["new_target"], "bar");
+
+    Ok(())
+}
+
+#[test]
+fn test_verbose_json_remove() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target.txt", "link.txt")?;
+
+    let output = ctx.run_slinky(&["-v", "-n", "--format", "json", "remove"])
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let records: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(records[0]["action"], "remove");
+    assert!(records[0].get("new_target").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_list_null_origins() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target", "new\nline")?;
+
+    ctx.run_slinky(&["list", "-0"])
+        .success()
+        .stdout(predicate::str::diff("./new\nline\0"));
+
+    Ok(())
+}
+
+#[test]
+fn test_from_stdin0() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let chosen = ctx.create_symlink("target", "new\nline")?;
+    let other = ctx.create_symlink("target", "other")?;
+    ctx.create_file("regular.txt", "")?;
+
+    ctx.run_slinky_with_stdin(&["--from-stdin0", "remove"], "new\nline\0regular.txt\0")
+        .code(1)
+        .stderr(predicate::str::contains("regular.txt: not a symlink"));
+
+    assert!(std::fs::symlink_metadata(&chosen).is_err());
+    assert!(std::fs::symlink_metadata(&other).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_from_stdin0_applies_filters() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("real.txt", "")?;
+    ctx.create_symlink("real.txt", "attached")?;
+    ctx.create_symlink("missing.txt", "dangling")?;
+
+    ctx.run_slinky_with_stdin(&["--from-stdin0", "-x", "list", "-0"], "attached\0dangling\0")
+        .success()
+        .stdout(predicate::str::diff("dangling\0"));
+
+    Ok(())
+}
+
+#[test]
+fn test_json_non_utf8_name() -> Result<(), Box<dyn std::error::Error>> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let ctx = TestContext::new()?;
+    std::os::unix::fs::symlink("a", ctx.path().join(OsStr::from_bytes(b"l\xff")))?;
+
+    for format in ["json", "ndjson"] {
+        let output = ctx.run_slinky(&["--format", format, "list"])
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let records = serde_json::Deserializer::from_slice(&output).into_iter::<serde_json::Value>();
+        let origins: Vec<_> = records
+            .map(|r| r.map(|r| r.get(0).unwrap_or(&r)["origin"].clone()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(origins, ["./l\u{fffd}"]);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use predicates::prelude::*;
+use std::fs;
+
+mod common;
+use common::TestContext;
+
+#[test]
+fn test_parallel_list_sorted() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    for name in ["c", "a/z", "b", "a/y/x"] {
+        ctx.create_symlink("target", name)?;
+    }
+
+    ctx.run_slinky(&["-j", "4", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::diff("./a/y/x\n./a/z\n./b\n./c\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_parallel_max_depth() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target", "top")?;
+    ctx.create_symlink("target", "sub/deep")?;
+
+    ctx.run_slinky(&["-j", "2", "-d", "1", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::diff("./top\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_parallel_tidy() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let mut links = Vec::new();
+    for dir in 0..4 {
+        for i in 0..25 {
+            links.push(ctx.create_symlink("foo/../bar", &format!("d{dir}/link{i}"))?);
+        }
+    }
+
+    ctx.run_slinky(&["-j", "0", "tidy"])
+        .success()
+        .stderr(predicate::str::contains("tidy: 100 changed, 0 skipped, 0 failed"));
+
+    for link in links {
+        assert_eq!(fs::read_link(link)?.to_str().unwrap(), "bar");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parallel_verbose_output_is_deterministic() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    for i in 0..20 {
+        ctx.create_symlink("a/./b", &format!("dir{}/link{i}", i % 3))?;
+        ctx.create_symlink("a/./b", &format!("link{i:02}"))?;
+    }
+
+    let run = || ctx.run_slinky(&["-j", "4", "-n", "-v", "--format", "tsv", "tidy"])
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let first = run();
+    assert_eq!(first, run());
+    let origins = String::from_utf8(first)?
+        .lines()
+        .map(|l| l.split('\t').next().unwrap().to_string())
+        .collect::<Vec<_>>();
+    let mut sorted = origins.clone();
+    sorted.sort();
+    assert_eq!(origins, sorted);
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use std::fs;
+use std::os::unix::fs::symlink;
+
+mod common;
+use common::TestContext;
+
+#[test]
+fn test_save_plan_makes_no_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let link = ctx.create_symlink("foo/../bar", "link.txt")?;
+
+    ctx.run_slinky(&["--save-plan", "plan.json", "tidy"])
+        .success();
+
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "foo/../bar");
+    let plan = fs::read_to_string(ctx.path().join("plan.json"))?;
+    assert!(plan.contains("\"action\": \"retarget\""));
+    assert!(plan.contains("\"old_target\": \"foo/../bar\""));
+    assert!(plan.contains("\"target\": \"bar\""));
+
+    Ok(())
+}
+
+#[test]
+fn test_save_plan_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target.txt", "link.txt")?;
+
+    ctx.run_slinky(&["--save-plan", "-", "remove"])
+        .success()
+        .stdout(predicate::str::contains("\"action\": \"remove\""));
+
+    assert!(fs::symlink_metadata(ctx.path().join("link.txt")).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_plan() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let link = ctx.create_symlink("foo/../bar", "link.txt")?;
+    let removed = ctx.create_symlink("target.txt", "removed.txt")?;
+
+    ctx.run_slinky(&["--save-plan", "tidy.json", "tidy"])
+        .success();
+    ctx.run_slinky(&["-o", "removed", "--save-plan", "remove.json", "remove"])
+        .success();
+
+    ctx.run_slinky(&["apply", "tidy.json"])
+        .success();
+    ctx.run_slinky(&["-v", "apply", "remove.json"])
+        .success()
+        .stdout(predicate::str::contains("remove"));
+
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "bar");
+    assert!(fs::symlink_metadata(&removed).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_plan_from_other_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("real.txt", "content")?;
+    let link = ctx.create_symlink("real.txt", "link.txt")?;
+
+    ctx.run_slinky(&["--save-plan", "plan.json", "to-hardlink"])
+        .success();
+
+    fs::create_dir(ctx.path().join("elsewhere"))?;
+    let mut cmd = ctx.slinky_cmd();
+    cmd.current_dir(ctx.path().join("elsewhere"));
+    cmd.args(["apply", "../plan.json"]);
+    cmd.assert().success();
+
+    let metadata = fs::symlink_metadata(&link)?;
+    assert!(metadata.is_file());
+    assert_eq!(fs::read_to_string(&link)?, "content");
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_plan_refuses_changed_link() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let link = ctx.create_symlink("foo/../bar", "link.txt")?;
+
+    ctx.run_slinky(&["--save-plan", "plan.json", "tidy"])
+        .success();
+
+    fs::remove_file(&link)?;
+    symlink("somewhere/else", &link)?;
+
+    ctx.run_slinky(&["apply", "plan.json"])
+        .stderr(predicate::str::contains("target changed since plan was made"));
+
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "somewhere/else");
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_plan_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let link = ctx.create_symlink("foo/../bar", "link.txt")?;
+
+    ctx.run_slinky(&["--save-plan", "plan.json", "tidy"])
+        .success();
+    ctx.run_slinky(&["--dry-run", "apply", "plan.json"])
+        .success();
+
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "foo/../bar");
+
+    Ok(())
+}
+
+#[test]
+fn test_save_plan_rejects_list() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+
+    ctx.run_slinky(&["--save-plan", "plan.json", "list"])
+        .failure()
+        .stderr(predicate::str::contains("--save-plan cannot be used with list"));
+
+    Ok(())
+}
@@ -462,3 +462,99 @@ fn test_create_tree_force_missing_target_preserves_origin() -> Result<(), Box<dy
 
     Ok(())
 }
+
+#[test]
+fn test_create_link_force_overwrite_dangling_symlink() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("target.txt", "content")?;
+    let existing_symlink = ctx.create_symlink("missing.txt", "existing_link.txt")?;
+
+    ctx.run_slinky_ln(&["target.txt", "existing_link.txt", "--force"])
+        .success();
+
+    assert_eq!(fs::read_link(&existing_symlink)?.to_str().unwrap(), "target.txt");
+    // the temporary link used for the swap should not be left behind
+    assert_eq!(fs::read_dir(ctx.path())?.count(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_create_hardlink_force_overwrite_file() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("target.txt", "content")?;
+    let existing = ctx.create_file("existing.txt", "old content")?;
+
+    ctx.run_slinky_ln(&["target.txt", "existing.txt", "--force", "--hard"])
+        .success();
+
+    let metadata = fs::symlink_metadata(&existing)?;
+    assert!(metadata.is_file());
+    assert_eq!(fs::read_to_string(&existing)?, "content");
+
+    Ok(())
+}
+
+#[test]
+fn test_batch() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("a.txt", "a")?;
+    ctx.create_file("b.txt", "b")?;
+    ctx.create_file("links/old", "old")?;
+    ctx.create_file(
+        "batch.txt",
+        "# target\torigin\tflags\n\
+         a.txt\tlinks/a\n\
+         \n\
+         b.txt\tlinks/old\t--force\n\
+         missing.txt\tlinks/missing\n\
+         a.txt\tlinks/both\t-b -r\n\
+         a.txt\n\
+         b.txt\tlinks/hard\t--hard\n",
+    )?;
+
+    ctx.run_slinky_ln(&["--batch", "batch.txt", "--relative"])
+        .code(1)
+        .stderr(predicate::str::contains("line 5: Target does not exist"))
+        .stderr(predicate::str::contains("line 6: the argument '--absolute' cannot be used with '--relative'"))
+        .stderr(predicate::str::contains("line 7: expected a target and an origin"))
+        .stderr(predicate::str::contains("slinky-ln: 3 created, 0 skipped, 3 failed"));
+
+    assert_eq!(fs::read_link(ctx.path().join("links/a"))?.to_str().unwrap(), "../a.txt");
+    assert_eq!(fs::read_link(ctx.path().join("links/old"))?.to_str().unwrap(), "../b.txt");
+    // --hard on the line replaces --relative rather than conflicting with it
+    assert!(fs::symlink_metadata(ctx.path().join("links/hard"))?.is_file());
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_unreadable() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+
+    ctx.run_slinky_ln(&["--batch", "missing.txt"])
+        .code(2)
+        .stderr(predicate::str::contains("missing.txt: cannot read batch"));
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_null_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("a\tb.txt", "a")?;
+    fs::create_dir(ctx.path().join("links"))?;
+
+    ctx.run_slinky_ln_with_stdin(&["--batch", "-", "-0", "--allow-dangling"], "a\tb.txt\0links/\0gone\0links/gone\0")
+        .success()
+        .stderr(predicate::str::contains("slinky-ln: 2 created, 0 skipped, 0 failed"));
+
+    assert_eq!(fs::read_link(ctx.path().join("links/a\tb.txt"))?.to_str().unwrap(), "a\tb.txt");
+    assert_eq!(fs::read_link(ctx.path().join("links/gone"))?.to_str().unwrap(), "gone");
+
+    ctx.run_slinky_ln(&["a.txt", "--batch", "-"])
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    Ok(())
+}
@@ -1,6 +1,8 @@
 use predicates::prelude::*;
 use std::fs;
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::fs::symlink;
+use std::path::PathBuf;
 
 mod common;
 use common::TestContext;
@@ -565,6 +567,29 @@ fn test_remove_no_symlinks() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn test_replace_with_target_shared() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("real", "content")?;
+    ctx.create_file("realdir/file", "")?;
+    let a = ctx.create_symlink("../real", "links/a")?;
+    let b = ctx.create_symlink("../real", "links/b")?;
+    let dir = ctx.create_symlink("../realdir", "links/dir")?;
+
+    // only one of the links can take the file; the other is left alone
+    ctx.run_slinky(&["links", "replace-with-target"])
+        .code(1)
+        .stderr(predicate::str::contains("target no longer exists"))
+        .stderr(predicate::str::contains("replace-with-target: 2 changed, 0 skipped, 1 failed"));
+    let (moved, kept) = if a.is_symlink() { (&b, &a) } else { (&a, &b) };
+    assert_eq!(fs::read_to_string(moved)?, "content");
+    assert_eq!(fs::read_link(kept)?, PathBuf::from("../real"));
+    assert!(fs::symlink_metadata(&dir)?.is_dir());
+    assert!(dir.join("file").exists());
+
+    Ok(())
+}
+
 #[test]
 fn test_remove() -> Result<(), Box<dyn std::error::Error>> {
     let ctx = TestContext::new()?;
@@ -574,7 +599,7 @@ fn test_remove() -> Result<(), Box<dyn std::error::Error>> {
         .success();
 
     assert!(!link.exists());
-    assert!(!fs::symlink_metadata(link).is_ok());
+    assert!(fs::symlink_metadata(link).is_err());
 
     Ok(())
 }
@@ -660,3 +685,543 @@ fn test_to_tree_file_symlink() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_exit_status_all_ok() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("foo/../bar", "link.txt")?;
+    ctx.create_symlink("bar", "tidy.txt")?;
+
+    ctx.run_slinky(&["tidy"])
+        .code(0)
+        .stderr(predicate::str::contains("tidy: 1 changed, 1 skipped, 0 failed"));
+
+    Ok(())
+}
+
+#[test]
+fn test_exit_status_some_failed() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target", "fails")?;
+    ctx.create_symlink("target", "succeeds")?;
+
+    ctx.run_slinky(&["exec", "case $1 in *fails) exit 3;; esac"])
+        .code(1)
+        .stderr(predicate::str::contains("exit status: 3"))
+        .stderr(predicate::str::contains("exec: 1 ran, 0 skipped, 1 failed"));
+
+    Ok(())
+}
+
+#[test]
+fn test_exit_status_fatal() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+
+    ctx.run_slinky(&["missing_dir", "list"])
+        .code(2);
+    ctx.run_slinky(&["edit-target", "(", "x"])
+        .code(2);
+
+    Ok(())
+}
+
+#[test]
+fn test_multiple_roots() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target", "one/link1")?;
+    ctx.create_symlink("target", "two/link2")?;
+    ctx.create_symlink("target", "three/link3")?;
+
+    ctx.run_slinky(&["one", "two", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::diff("one/link1\ntwo/link2\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_multiple_roots_flags_after_command() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("config/real", "")?;
+    ctx.create_symlink("real", "config/attached")?;
+    ctx.create_symlink("missing", "config/dangling")?;
+    ctx.create_symlink("missing", "bin/dangling")?;
+
+    ctx.run_slinky(&["config", "bin", "list", "-x"])
+        .success()
+        .stdout(predicate::str::diff("config/dangling -> missing\nbin/dangling -> missing\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_multiple_roots_overlapping() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target", "outer/link1")?;
+    ctx.create_symlink("target", "outer/inner/link2")?;
+
+    ctx.run_slinky(&["outer", "outer/inner", "./outer/inner/", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::contains("link1").count(1))
+        .stdout(predicate::str::contains("link2").count(1));
+
+    ctx.run_slinky(&["-j", "2", "outer/inner", "outer", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::diff("outer/inner/link2\nouter/link1\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_multiple_roots_merged_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("foo/../bar", "one/link")?;
+    ctx.create_symlink("foo/../bar", "two/link")?;
+
+    ctx.run_slinky(&["one", "two", "one", "tidy"])
+        .success()
+        .stderr(predicate::str::contains("tidy: 2 changed, 0 skipped, 0 failed"));
+
+    Ok(())
+}
+
+#[test]
+fn test_multiple_roots_one_missing() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target", "one/link")?;
+
+    ctx.run_slinky(&["one", "missing", "list"])
+        .code(2)
+        .stderr(predicate::str::contains("missing: No such file or directory"));
+
+    Ok(())
+}
+
+#[test]
+fn test_follow_directory_symlinks() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target", "real/sub/inner")?;
+    ctx.create_symlink("../real", "tree/via")?;
+
+    ctx.run_slinky(&["tree", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::diff("tree/via\n"));
+
+    ctx.run_slinky(&["-L", "tree", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::diff("tree/via\ntree/via/sub/inner\n"));
+
+    ctx.run_slinky(&["-L", "-j", "2", "tree", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::diff("tree/via\ntree/via/sub/inner\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_follow_max_depth() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target", "real/sub/inner")?;
+    ctx.create_symlink("../real", "tree/via")?;
+
+    // via is at depth 2 from the temp dir, so inner is at depth 4
+    ctx.run_slinky(&["-L", "-d", "3", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::contains("via/sub/inner").not());
+
+    ctx.run_slinky(&["-L", "-d", "4", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::contains("./tree/via/sub/inner"));
+
+    Ok(())
+}
+
+#[test]
+fn test_follow_loop() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("..", "tree/sub/up")?;
+    ctx.create_symlink("../tree", "other/back")?;
+
+    for jobs in ["1", "4"] {
+        ctx.run_slinky(&["-L", "-j", jobs, "other", "list", "--origin-only"])
+            .success()
+            .stdout(predicate::str::diff("other/back\nother/back/sub/up\n"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_follow_reaches_link_twice() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("../b", "a/tob")?;
+    ctx.create_symlink("../a", "b/toa")?;
+
+    // b/dang is also reachable as a/tob/dang, but is only removed once
+    for jobs in ["1", "4"] {
+        ctx.create_symlink("nothing", "b/dang")?;
+        ctx.run_slinky(&["-L", "-j", jobs, "-x", "rm"])
+            .success()
+            .stderr(predicate::str::contains("no longer a symlink").not());
+        assert!(fs::symlink_metadata(ctx.path().join("b/dang")).is_err());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_one_file_system() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    // /dev is normally its own filesystem, and holds symlinks such as /dev/stdin
+    if fs::metadata("/dev")?.dev() == fs::metadata(ctx.path())?.dev() {
+        return Ok(());
+    }
+    ctx.create_symlink("/dev", "devices")?;
+
+    for jobs in ["1", "4"] {
+        ctx.run_slinky(&["-L", "-j", jobs, "-d", "2", "list", "--origin-only"])
+            .success()
+            .stdout(predicate::str::contains("./devices/"));
+
+        ctx.run_slinky(&["-L", "-X", "-j", jobs, "-d", "2", "list", "--origin-only"])
+            .success()
+            .stdout(predicate::str::diff("./devices\n"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_list_status_loops_and_chains() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("loop_b", "loop_a")?;
+    ctx.create_symlink("loop_a", "loop_b")?;
+    ctx.create_symlink("missing", "broken")?;
+    ctx.create_file("real.txt", "")?;
+    ctx.create_symlink("real.txt", "chain3")?;
+    ctx.create_symlink("chain3", "chain2")?;
+    ctx.create_symlink("chain2", "chain1")?;
+
+    ctx.run_slinky(&["list", "--status"])
+        .success()
+        .stdout(predicate::str::contains("looping: ./loop_a -> loop_b"))
+        .stdout(predicate::str::contains("looping: ./loop_b -> loop_a"))
+        .stdout(predicate::str::contains("dangling: ./broken -> missing"))
+        .stdout(predicate::str::contains("attached: ./chain1 -> chain2"));
+
+    ctx.run_slinky(&["--max-hops", "2", "list", "--status"])
+        .success()
+        .stdout(predicate::str::contains("too-long: ./chain1 -> chain2"))
+        .stdout(predicate::str::contains("attached: ./chain2 -> chain3"));
+
+    Ok(())
+}
+
+#[test]
+fn test_only_looping() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("self", "self")?;
+    ctx.create_symlink("self", "into_loop")?;
+    ctx.create_symlink("missing", "broken")?;
+
+    ctx.run_slinky(&["--only-looping", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::contains("./self"))
+        .stdout(predicate::str::contains("./into_loop"))
+        .stdout(predicate::str::contains("broken").not());
+
+    // looping links count as dangling
+    ctx.run_slinky(&["-x", "list", "--origin-only"])
+        .success()
+        .stdout(predicate::str::contains("./self"))
+        .stdout(predicate::str::contains("./broken"));
+
+    Ok(())
+}
+
+#[test]
+fn test_resolve() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let real = ctx.create_file("real/file.txt", "")?;
+    let alt = ctx.create_symlink(real.to_str().unwrap(), "alternatives/file")?;
+    ctx.create_symlink(alt.to_str().unwrap(), "bin/file")?;
+    ctx.create_symlink("../bin/file", "local/file")?;
+    ctx.create_symlink("../alternatives/gone", "local/broken")?;
+
+    let real = fs::canonicalize(&real)?;
+    ctx.run_slinky(&["local", "resolve"])
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "attached: local/file -> ../bin/file (relative) -> {} (absolute) -> {} (absolute) => {}",
+            alt.display(),
+            real.display(),
+            real.display()
+        )))
+        .stdout(predicate::str::contains(
+            "dangling: local/broken -> ../alternatives/gone (relative) => local/../alternatives/gone",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_resolve_loop() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("b", "loop/a")?;
+    ctx.create_symlink("a", "loop/b")?;
+
+    ctx.run_slinky(&["loop", "-o", "a$", "resolve"])
+        .success()
+        .stdout(predicate::str::diff(
+            "looping: loop/a -> b (relative) -> a (relative) => loop/a\n",
+        ));
+
+    let output = ctx.run_slinky(&["loop", "-o", "a$", "--format", "json", "resolve"])
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let records: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(records[0]["status"], "looping");
+    assert_eq!(records[0]["hops"].as_array().unwrap().len(), 2);
+    assert_eq!(records[0]["hops"][1]["link"], "loop/b");
+    assert_eq!(records[0]["hops"][1]["absolute"], false);
+
+    Ok(())
+}
+
+#[test]
+fn test_flatten() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let real = ctx.create_file("real/file.txt", "")?;
+    let real = fs::canonicalize(real)?;
+    ctx.create_symlink("../real/file.txt", "alt/step2")?;
+    ctx.create_symlink("step2", "alt/step1")?;
+    let relative = ctx.create_symlink("../alt/step1", "bin/relative")?;
+    let absolute = ctx.create_symlink(ctx.path().join("alt/step1").to_str().unwrap(), "bin/absolute")?;
+    let direct = ctx.create_symlink("../real/file.txt", "bin/direct")?;
+    ctx.create_symlink("../alt/gone", "bin/broken")?;
+
+    ctx.run_slinky(&["bin", "flatten"])
+        .success()
+        .stderr(predicate::str::contains("flatten: 2 changed, 2 skipped, 0 failed"));
+
+    assert_eq!(fs::read_link(&relative)?, PathBuf::from("../real/file.txt"));
+    assert_eq!(fs::read_link(&absolute)?, real);
+    assert_eq!(fs::read_link(&direct)?, PathBuf::from("../real/file.txt"));
+
+    Ok(())
+}
+
+#[test]
+fn test_flatten_hop_cap() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("real.txt", "")?;
+    ctx.create_symlink("real.txt", "step3")?;
+    ctx.create_symlink("step3", "step2")?;
+    ctx.create_symlink("step2", "step1")?;
+    let link = ctx.create_symlink("../step1", "bin/link")?;
+
+    ctx.run_slinky(&["bin", "flatten", "--hops", "1"]).success();
+    assert_eq!(fs::read_link(&link)?, PathBuf::from("../step2"));
+
+    ctx.run_slinky(&["bin", "flatten", "--hops", "1"]).success();
+    assert_eq!(fs::read_link(&link)?, PathBuf::from("../step3"));
+
+    ctx.run_slinky(&["bin", "flatten"]).success();
+    assert_eq!(fs::read_link(&link)?, PathBuf::from("../real.txt"));
+
+    Ok(())
+}
+
+#[test]
+fn test_no_canonicalize_keeps_aliases() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("releases/v2/file.txt", "")?;
+    ctx.create_symlink("releases/v2", "current")?;
+    let root = fs::canonicalize(ctx.path())?;
+    let relative = ctx.create_symlink("../current/./file.txt", "bin/relative")?;
+    let absolute = ctx.create_symlink(root.join("current/file.txt").to_str().unwrap(), "bin/absolute")?;
+
+    ctx.run_slinky(&["bin", "to-absolute", "--no-canonicalize"]).success();
+    assert_eq!(fs::read_link(&relative)?, root.join("current/file.txt"));
+
+    ctx.run_slinky(&["bin", "to-relative", "--no-canonicalize"]).success();
+    assert_eq!(fs::read_link(&relative)?, PathBuf::from("../current/file.txt"));
+    assert_eq!(fs::read_link(&absolute)?, PathBuf::from("../current/file.txt"));
+
+    // canonicalizing resolves the alias away
+    ctx.run_slinky(&["bin", "to-absolute"]).success();
+    assert_eq!(fs::read_link(&absolute)?, root.join("releases/v2/file.txt"));
+
+    Ok(())
+}
+
+#[test]
+fn test_to_relative_within() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let root = fs::canonicalize(ctx.path())?;
+    ctx.create_file("bundle/lib/inside.so", "")?;
+    ctx.create_file("system/outside.so", "")?;
+    let inside = ctx.create_symlink(root.join("bundle/lib/inside.so").to_str().unwrap(), "bundle/bin/inside")?;
+    let escaping = ctx.create_symlink("../../system/outside.so", "bundle/bin/escaping")?;
+    let outside = ctx.create_symlink(root.join("system/outside.so").to_str().unwrap(), "bundle/bin/outside")?;
+
+    ctx.run_slinky(&["bundle", "to-relative", "--within", "bundle"])
+        .success()
+        .stderr(predicate::str::contains("to-relative: 2 changed"));
+
+    assert_eq!(fs::read_link(&inside)?, PathBuf::from("../lib/inside.so"));
+    assert_eq!(fs::read_link(&escaping)?, root.join("system/outside.so"));
+    assert_eq!(fs::read_link(&outside)?, root.join("system/outside.so"));
+
+    ctx.run_slinky(&["bundle", "to-relative", "--within", "missing"])
+        .code(2)
+        .stderr(predicate::str::contains("missing: not a directory"));
+
+    Ok(())
+}
+
+#[test]
+fn test_relocate() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let root = fs::canonicalize(ctx.path())?;
+    ctx.create_file("srv/new/data.txt", "")?;
+    let absolute = ctx.create_symlink(root.join("srv/old/data.txt").to_str().unwrap(), "links/absolute")?;
+    let relative = ctx.create_symlink("../srv/old/data.txt", "links/relative")?;
+    let missing = ctx.create_symlink(root.join("srv/old/gone.txt").to_str().unwrap(), "links/missing")?;
+    // shares a string prefix with srv/old, but not a path prefix
+    let sibling = ctx.create_symlink(root.join("srv/older/data.txt").to_str().unwrap(), "links/sibling")?;
+
+    ctx.run_slinky(&["links", "relocate", "--from", "srv/old", "--to", "srv/new"])
+        .success()
+        .stderr(predicate::str::contains("new target does not exist: links/missing"))
+        .stderr(predicate::str::contains("relocate: 3 changed, 0 skipped, 0 failed"));
+
+    assert_eq!(fs::read_link(&absolute)?, root.join("srv/new/data.txt"));
+    assert_eq!(fs::read_link(&relative)?, PathBuf::from("../srv/new/data.txt"));
+    assert_eq!(fs::read_link(&missing)?, root.join("srv/new/gone.txt"));
+    assert_eq!(fs::read_link(&sibling)?, root.join("srv/older/data.txt"));
+
+    Ok(())
+}
+
+#[test]
+fn test_relocate_file() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let root = fs::canonicalize(ctx.path())?;
+    ctx.create_file("newfile", "content")?;
+    let absolute = ctx.create_symlink(root.join("oldfile").to_str().unwrap(), "links/absolute")?;
+    let relative = ctx.create_symlink("../oldfile", "links/relative")?;
+
+    ctx.run_slinky(&["links", "relocate", "--from", "oldfile", "--to", "newfile"])
+        .success()
+        .stderr(predicate::str::contains("relocate: 2 changed, 0 skipped, 0 failed"));
+
+    assert_eq!(fs::read_link(&absolute)?, root.join("newfile"));
+    assert_eq!(fs::read_link(&relative)?, PathBuf::from("../newfile"));
+    assert_eq!(fs::read_to_string(&relative)?, "content");
+
+    Ok(())
+}
+
+#[test]
+fn test_relocate_existing_only() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let root = fs::canonicalize(ctx.path())?;
+    ctx.create_file("new/data.txt", "")?;
+    let found = ctx.create_symlink(root.join("old/data.txt").to_str().unwrap(), "links/found")?;
+    let missing = ctx.create_symlink(root.join("old/gone.txt").to_str().unwrap(), "links/missing")?;
+
+    let from = root.join("old");
+    let to = root.join("new");
+    ctx.run_slinky(&[
+        "links",
+        "relocate",
+        "--from",
+        from.to_str().unwrap(),
+        "--to",
+        to.to_str().unwrap(),
+        "--existing-only",
+    ])
+    .success()
+    .stderr(predicate::str::contains("relocate: 1 changed, 1 skipped, 0 failed"));
+
+    assert_eq!(fs::read_link(&found)?, root.join("new/data.txt"));
+    assert_eq!(fs::read_link(&missing)?, root.join("old/gone.txt"));
+
+    Ok(())
+}
+
+#[test]
+fn test_repair() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let root = fs::canonicalize(ctx.path())?;
+    ctx.create_file("moved/docs/guide.md", "")?;
+    ctx.create_file("moved/notes/guide.md", "")?;
+    ctx.create_file("moved/readme.md", "")?;
+    let relative = ctx.create_symlink("../old/readme.md", "links/relative")?;
+    let absolute = ctx.create_symlink(root.join("old/readme.md").to_str().unwrap(), "links/absolute")?;
+    // only one candidate shares docs/guide.md
+    let suffix = ctx.create_symlink("../old/docs/guide.md", "links/suffix")?;
+    let ambiguous = ctx.create_symlink("../old/guide.md", "links/ambiguous")?;
+    let lost = ctx.create_symlink("../old/lost.md", "links/lost")?;
+
+    ctx.run_slinky(&["links", "repair", "--search", "moved"])
+        .success()
+        .stderr(predicate::str::contains("several candidate targets, use --choose to pick one: links/ambiguous"))
+        .stderr(predicate::str::contains("moved/notes/guide.md"))
+        .stderr(predicate::str::contains("no candidate target found: links/lost"))
+        .stderr(predicate::str::contains("repair: 3 changed, 2 skipped, 0 failed"));
+
+    assert_eq!(fs::read_link(&relative)?, PathBuf::from("../moved/readme.md"));
+    assert_eq!(fs::read_link(&absolute)?, root.join("moved/readme.md"));
+    assert_eq!(fs::read_link(&suffix)?, PathBuf::from("../moved/docs/guide.md"));
+    assert_eq!(fs::read_link(&ambiguous)?, PathBuf::from("../old/guide.md"));
+    assert_eq!(fs::read_link(&lost)?, PathBuf::from("../old/lost.md"));
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_choose() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("a/guide.md", "")?;
+    ctx.create_file("b/guide.md", "")?;
+    let first = ctx.create_symlink("../old/guide.md", "links/first")?;
+    let second = ctx.create_symlink("../gone/guide.md", "links/second")?;
+
+    // the first link gets candidate 2, the second an invalid answer, then none
+    ctx.run_slinky_with_stdin(&["links", "repair", "--search", "a", "--search", "b", "--choose"], "2\n9\n\n")
+        .success()
+        .stderr(predicate::str::contains("1) "))
+        .stderr(predicate::str::contains("no candidate chosen: links/second"))
+        .stderr(predicate::str::contains("repair: 1 changed, 1 skipped, 0 failed"));
+
+    assert_eq!(fs::read_link(&first)?, PathBuf::from("../b/guide.md"));
+    assert_eq!(fs::read_link(&second)?, PathBuf::from("../gone/guide.md"));
+
+    Ok(())
+}
+
+#[test]
+fn test_interactive_refuses_without_terminal() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("target.txt", "")?;
+    let link = ctx.create_symlink("./target.txt", "link")?;
+
+    ctx.run_slinky_with_stdin(&["-i", "tidy"], "y\n")
+        .success()
+        .stderr(predicate::str::contains("stdin is not a terminal, refusing every change"))
+        .stderr(predicate::str::contains("tidy: 0 changed, 1 skipped, 0 failed"));
+    assert_eq!(fs::read_link(&link)?, PathBuf::from("./target.txt"));
+
+    ctx.run_slinky_with_stdin(&["--interactive", "exec", "touch ran"], "y\n")
+        .success()
+        .stderr(predicate::str::contains("exec: 0 ran, 1 skipped, 0 failed"));
+    assert!(!ctx.path().join("ran").exists());
+
+    ctx.run_slinky(&["-i", "-n", "tidy"]).code(2);
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use predicates::prelude::*;
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+use common::TestContext;
+
+fn create_farm(ctx: &TestContext) -> Result<(), Box<dyn std::error::Error>> {
+    ctx.create_file("farm/bin/tool-1.2", "")?;
+    ctx.create_symlink("tool-1.2", "farm/bin/tool")?;
+    ctx.create_symlink("/usr/lib/missing", "farm/lib/gone")?;
+    ctx.create_symlink("../bin", "farm/alternatives/bin")?;
+    Ok(())
+}
+
+#[test]
+fn test_snapshot() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_farm(&ctx)?;
+
+    ctx.run_slinky(&["farm", "snapshot", "-"])
+        .success()
+        .stdout(predicate::str::contains("\"origin\": \"alternatives/bin\""))
+        .stdout(predicate::str::contains("\"target\": \"/usr/lib/missing\""))
+        .stdout(predicate::str::contains("\"status\": \"dangling\""))
+        .stdout(predicate::str::contains("owner").not());
+
+    ctx.run_slinky(&["farm", "snapshot", "--metadata", "farm.json"]).success();
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(ctx.path().join("farm.json"))?)?;
+    assert_eq!(json["links"].as_array().unwrap().len(), 3);
+    assert!(json["links"][0]["owner"]["uid"].is_u64());
+
+    ctx.run_slinky(&["farm", "bin", "snapshot", "x.json"])
+        .code(2)
+        .stderr(predicate::str::contains("snapshot takes a single search root"));
+
+    Ok(())
+}
+
+#[test]
+fn test_restore() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_farm(&ctx)?;
+    ctx.run_slinky(&["farm", "snapshot", "--metadata", "farm.json"]).success();
+
+    // somewhere else entirely
+    ctx.run_slinky(&["restore", "farm.json", "--into", "copy"])
+        .success()
+        .stderr(predicate::str::contains("restore: 3 restored, 0 skipped, 0 failed"));
+    assert_eq!(fs::read_link(ctx.path().join("copy/bin/tool"))?, PathBuf::from("tool-1.2"));
+    assert_eq!(fs::read_link(ctx.path().join("copy/lib/gone"))?, PathBuf::from("/usr/lib/missing"));
+
+    // and in place, after a wipe
+    fs::remove_file(ctx.path().join("farm/bin/tool"))?;
+    fs::remove_dir_all(ctx.path().join("farm/lib"))?;
+    ctx.run_slinky(&["restore", "farm.json", "--into", "farm"])
+        .success()
+        .stderr(predicate::str::contains("restore: 2 restored, 0 skipped, 0 failed"));
+    assert_eq!(fs::read_link(ctx.path().join("farm/bin/tool"))?, PathBuf::from("tool-1.2"));
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_symlinked_root() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_farm(&ctx)?;
+    ctx.create_symlink("farm", "current")?;
+
+    ctx.run_slinky(&["current", "snapshot", "farm.json"]).success();
+    ctx.run_slinky(&["restore", "farm.json", "--into", "copy"])
+        .success()
+        .stderr(predicate::str::contains("restore: 3 restored, 0 skipped, 0 failed"));
+    assert_eq!(fs::read_link(ctx.path().join("copy/bin/tool"))?, PathBuf::from("tool-1.2"));
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_conflicts() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_farm(&ctx)?;
+    ctx.run_slinky(&["farm", "snapshot", "farm.json"]).success();
+    ctx.create_file("copy/bin/tool", "mine")?;
+    ctx.create_symlink("elsewhere", "copy/lib/gone")?;
+
+    ctx.run_slinky(&["restore", "farm.json", "--into", "copy"])
+        .code(1)
+        .stderr(predicate::str::contains("copy/bin/tool: existing file is in the way, use --force to replace it"))
+        .stderr(predicate::str::contains("restore: 1 restored, 0 skipped, 2 failed"));
+
+    ctx.run_slinky(&["-v", "restore", "--force", "farm.json", "--into", "copy"])
+        .success()
+        .stdout(predicate::str::contains("restore: copy/lib/gone -> /usr/lib/missing"))
+        .stderr(predicate::str::contains("restore: 2 restored, 0 skipped, 0 failed"));
+    assert_eq!(fs::read_link(ctx.path().join("copy/bin/tool"))?, PathBuf::from("tool-1.2"));
+
+    Ok(())
+}
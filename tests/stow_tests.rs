@@ -0,0 +1,117 @@
+use predicates::prelude::*;
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+use common::TestContext;
+
+fn create_packages(ctx: &TestContext) -> Result<(), Box<dyn std::error::Error>> {
+    ctx.create_file("dotfiles/zsh/.zshrc", "")?;
+    ctx.create_file("dotfiles/vim/.vimrc", "")?;
+    ctx.create_file("dotfiles/vim/.config/nvim/init.lua", "")?;
+    ctx.create_file("dotfiles/git/.config/git/config", "")?;
+    fs::create_dir(ctx.path().join("home"))?;
+    Ok(())
+}
+
+#[test]
+fn test_stow_folds_and_unfolds() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_packages(&ctx)?;
+    let home = ctx.path().join("home");
+
+    ctx.run_slinky(&["stow", "dotfiles/zsh", "dotfiles/vim", "--into", "home"])
+        .success()
+        .stderr(predicate::str::contains("stow: 3 changed, 0 skipped, 0 failed"));
+    assert_eq!(fs::read_link(home.join(".zshrc"))?, PathBuf::from("../dotfiles/zsh/.zshrc"));
+    assert_eq!(fs::read_link(home.join(".config"))?, PathBuf::from("../dotfiles/vim/.config"));
+
+    // git needs .config too, so vim's link to it is split up
+    ctx.run_slinky(&["stow", "dotfiles/git", "--into", "home"])
+        .success()
+        .stderr(predicate::str::contains("stow: 4 changed, 0 skipped, 0 failed"));
+    assert!(home.join(".config").symlink_metadata()?.is_dir());
+    assert_eq!(
+        fs::read_link(home.join(".config/nvim"))?,
+        PathBuf::from("../../dotfiles/vim/.config/nvim")
+    );
+    assert_eq!(fs::read_link(home.join(".config/git"))?, PathBuf::from("../../dotfiles/git/.config/git"));
+    assert!(home.join(".config/git/config").exists());
+
+    ctx.run_slinky(&["stow", "dotfiles/git", "--into", "home"])
+        .success()
+        .stderr(predicate::str::contains("stow: 0 changed, 1 skipped, 0 failed"));
+
+    // and folded back together when git goes
+    ctx.run_slinky(&["unstow", "dotfiles/git", "--into", "home"]).success();
+    assert_eq!(fs::read_link(home.join(".config"))?, PathBuf::from("../dotfiles/vim/.config"));
+
+    ctx.run_slinky(&["unstow", "dotfiles/vim", "dotfiles/zsh", "--into", "home"]).success();
+    assert_eq!(fs::read_dir(&home)?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_stow_default_target() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("home/dotfiles/zsh/.zshrc", "")?;
+
+    ctx.run_slinky(&["stow", "home/dotfiles/zsh"]).success();
+    assert_eq!(
+        fs::read_link(ctx.path().join("home/.zshrc"))?,
+        PathBuf::from("dotfiles/zsh/.zshrc")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_stow_conflicts() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_packages(&ctx)?;
+    ctx.create_file("home/.vimrc", "mine")?;
+    ctx.create_symlink("/elsewhere", "home/.config")?;
+
+    ctx.run_slinky(&["stow", "dotfiles/zsh", "dotfiles/vim", "--into", "home"])
+        .code(1)
+        .stderr(predicate::str::contains("existing file is in the way: "))
+        .stderr(predicate::str::contains("existing symlink is in the way: "))
+        .stderr(predicate::str::contains("stow: 0 changed, 0 skipped, 2 failed"));
+
+    // nothing is changed, not even what wasn't in the way
+    assert!(!ctx.path().join("home/.zshrc").exists());
+    assert_eq!(fs::read_to_string(ctx.path().join("home/.vimrc"))?, "mine");
+
+    Ok(())
+}
+
+#[test]
+fn test_restow() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_packages(&ctx)?;
+    let home = ctx.path().join("home");
+    ctx.run_slinky(&["stow", "dotfiles/zsh", "--into", "home"]).success();
+
+    fs::rename(ctx.path().join("dotfiles/zsh/.zshrc"), ctx.path().join("dotfiles/zsh/.zshenv"))?;
+    ctx.run_slinky(&["restow", "dotfiles/zsh", "--into", "home"]).success();
+    assert!(home.join(".zshrc").symlink_metadata().is_err());
+    assert_eq!(fs::read_link(home.join(".zshenv"))?, PathBuf::from("../dotfiles/zsh/.zshenv"));
+
+    Ok(())
+}
+
+#[test]
+fn test_stow_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_packages(&ctx)?;
+
+    ctx.run_slinky(&["-n", "-v", "--exclude", ".vimrc", "stow", "dotfiles/vim", "--into", "home"])
+        .success()
+        .stdout(predicate::str::contains("stow: link: "))
+        .stdout(predicate::str::contains(".vimrc").not())
+        .stderr(predicate::str::contains("stow: 1 planned, 0 skipped, 0 failed"));
+    assert_eq!(fs::read_dir(ctx.path().join("home"))?.count(), 0);
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use predicates::prelude::*;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+
+mod common;
+use common::TestContext;
+
+const MANIFEST: &str = r#"
+roots = ["links"]
+
+[[link]]
+origin = "links/relative"
+target = "files/a.txt"
+relative = true
+
+[[link]]
+origin = "links/plain"
+target = "../files/b.txt"
+
+[[link]]
+origin = "links/hard"
+target = "files/a.txt"
+hard = true
+
+[[link]]
+origin = "links/tree"
+target = "files/dir"
+tree = true
+"#;
+
+fn create_files(ctx: &TestContext) -> Result<(), Box<dyn std::error::Error>> {
+    ctx.create_file("files/a.txt", "a")?;
+    ctx.create_file("files/b.txt", "b")?;
+    ctx.create_file("files/dir/c.txt", "c")?;
+    ctx.create_file("setup/manifest.toml", &MANIFEST.replace("\"links", "\"../links").replace("\"files", "\"../files"))?;
+    fs::create_dir(ctx.path().join("links"))?;
+    Ok(())
+}
+
+#[test]
+fn test_sync() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_files(&ctx)?;
+    let links = ctx.path().join("links");
+    // one link is wrong and another is in the way
+    ctx.create_symlink("files/b.txt", "links/relative")?;
+    ctx.create_file("links/plain", "mine")?;
+
+    ctx.run_slinky(&["sync", "setup/manifest.toml"])
+        .code(1)
+        .stdout(predicate::str::contains("~ setup/../links/relative -> (files/b.txt => ../files/a.txt)"))
+        .stdout(predicate::str::contains("+ setup/../links/hard -> ../files/a.txt (hardlink)"))
+        .stdout(predicate::str::contains("+ setup/../links/tree -> ../files/dir (symlink tree)"))
+        .stderr(predicate::str::contains("links/plain: existing file is in the way, set force to replace it"))
+        .stderr(predicate::str::contains("sync: 3 changed, 0 skipped, 1 failed"));
+
+    assert_eq!(fs::read_link(links.join("relative"))?, PathBuf::from("../files/a.txt"));
+    assert_eq!(fs::read_to_string(links.join("plain"))?, "mine");
+    assert_eq!(
+        fs::metadata(links.join("hard"))?.ino(),
+        fs::metadata(ctx.path().join("files/a.txt"))?.ino()
+    );
+    assert!(links.join("tree/c.txt").is_symlink());
+
+    fs::remove_file(links.join("plain"))?;
+    ctx.run_slinky(&["sync", "setup/manifest.toml"])
+        .success()
+        .stdout(predicate::str::diff(
+            "+ setup/../links/plain -> ../files/b.txt\n",
+        ))
+        .stderr(predicate::str::contains("sync: 1 changed, 0 skipped, 0 failed"));
+    assert_eq!(fs::read_link(links.join("plain"))?, PathBuf::from("../files/b.txt"));
+
+    ctx.run_slinky(&["sync", "setup/manifest.toml"])
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("sync: 0 changed, 0 skipped, 0 failed"));
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_prune() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    create_files(&ctx)?;
+    let stray = ctx.create_symlink("../files/a.txt", "links/stray")?;
+    let nested = ctx.create_symlink("../../files/a.txt", "links/sub/stray")?;
+
+    ctx.run_slinky(&["-n", "sync", "--prune", "setup/manifest.toml"])
+        .success()
+        .stdout(predicate::str::contains("- ").count(2))
+        .stderr(predicate::str::contains("sync: 6 planned, 0 skipped, 0 failed"));
+    assert!(stray.is_symlink());
+
+    ctx.run_slinky(&["sync", "--prune", "setup/manifest.toml"])
+        .success()
+        .stdout(predicate::str::contains("- setup/../links/stray"))
+        .stderr(predicate::str::contains("sync: 6 changed, 0 skipped, 0 failed"));
+    assert!(!stray.is_symlink());
+    assert!(!nested.is_symlink());
+    // the symlinks making up a tree are kept
+    assert!(ctx.path().join("links/tree/c.txt").is_symlink());
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_invalid_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("manifest.toml", "[[link]]\norigin = \"a\"\ntarget = \"b\"\nrelative = true\nabsolute = true\n")?;
+    ctx.run_slinky(&["sync", "manifest.toml"])
+        .code(1)
+        .stderr(predicate::str::contains("a: only one of absolute, relative"));
+
+    ctx.create_file("manifest.toml", "[[link]]\norigin = \"a\"\n")?;
+    ctx.run_slinky(&["sync", "manifest.toml"])
+        .code(2)
+        .stderr(predicate::str::contains("manifest.toml: invalid manifest"));
+
+    Ok(())
+}
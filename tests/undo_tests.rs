@@ -0,0 +1,219 @@
+use predicates::prelude::*;
+use std::fs;
+
+mod common;
+use common::TestContext;
+
+#[test]
+fn test_undo_remove() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let link = ctx.create_symlink("target.txt", "link.txt")?;
+
+    ctx.run_slinky(&["remove"])
+        .success();
+    assert!(fs::symlink_metadata(&link).is_err());
+
+    ctx.run_slinky(&["undo"])
+        .success();
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "target.txt");
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_last_n() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("real.txt", "content")?;
+    let link = ctx.create_symlink("./real.txt", "link.txt")?;
+
+    ctx.run_slinky(&["tidy"])
+        .success();
+    ctx.run_slinky(&["to-absolute"])
+        .success();
+    assert!(fs::read_link(&link)?.is_absolute());
+
+    ctx.run_slinky(&["undo", "--last", "2"])
+        .success();
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "./real.txt");
+
+    // both journals are used up
+    ctx.run_slinky(&["undo"])
+        .failure()
+        .stderr(predicate::str::contains("nothing to undo"));
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_replace_with_target() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let target = ctx.create_file("real.txt", "content")?;
+    let link = ctx.create_symlink("real.txt", "link.txt")?;
+
+    ctx.run_slinky(&["replace-with-target"])
+        .success();
+    assert!(!target.exists());
+
+    ctx.run_slinky(&["undo"])
+        .success();
+    assert_eq!(fs::read_to_string(&target)?, "content");
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "real.txt");
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_to_tree() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("source/file.txt", "content")?;
+    let link = ctx.create_symlink("source", "link_to_dir")?;
+
+    ctx.run_slinky(&["-o", "link_to_dir$", "to-tree"])
+        .success();
+    assert!(!link.is_symlink());
+
+    ctx.run_slinky(&["undo"])
+        .success();
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "source");
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_journal_file() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let link = ctx.create_symlink("foo/../bar", "link.txt")?;
+
+    ctx.run_slinky(&["tidy"])
+        .success();
+
+    let journal = fs::read_dir(ctx.state_path())?.next().unwrap()?.path();
+    let contents = fs::read_to_string(&journal)?;
+    assert!(contents.contains("\"old_target\":\"foo/../bar\""));
+    assert!(contents.contains("\"timestamp\""));
+
+    ctx.run_slinky(&["undo", "--journal", journal.to_str().unwrap()])
+        .success();
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "foo/../bar");
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_refuses_changed_link() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let link = ctx.create_symlink("target.txt", "link.txt")?;
+
+    ctx.run_slinky(&["remove"])
+        .success();
+    ctx.create_file("link.txt", "new file")?;
+
+    ctx.run_slinky(&["undo"])
+        .stderr(predicate::str::contains("something already exists here"));
+    assert_eq!(fs::read_to_string(&link)?, "new file");
+
+    Ok(())
+}
+
+#[test]
+fn test_no_journal() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target.txt", "link.txt")?;
+
+    ctx.run_slinky(&["--no-journal", "remove"])
+        .success();
+
+    assert_eq!(fs::read_dir(ctx.state_path())?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_dry_run_not_journaled() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("target.txt", "link.txt")?;
+
+    ctx.run_slinky(&["--dry-run", "remove"])
+        .success();
+
+    assert_eq!(fs::read_dir(ctx.state_path())?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_sync_prune() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_file("files/a.txt", "a")?;
+    ctx.create_file("manifest.toml", "roots = [\"links\"]\n\n[[link]]\norigin = \"links/a\"\ntarget = \"../files/b.txt\"\nallow-dangling = true\n")?;
+    let listed = ctx.create_symlink("../files/a.txt", "links/a")?;
+    let stray = ctx.create_symlink("../files/a.txt", "links/stray")?;
+
+    ctx.run_slinky(&["sync", "--prune", "manifest.toml"])
+        .success()
+        .stderr(predicate::str::contains("sync: 2 changed, 0 skipped, 0 failed"));
+    assert!(fs::symlink_metadata(&stray).is_err());
+
+    ctx.run_slinky(&["undo"])
+        .success();
+    assert_eq!(fs::read_link(&stray)?.to_str().unwrap(), "../files/a.txt");
+    assert_eq!(fs::read_link(&listed)?.to_str().unwrap(), "../files/a.txt");
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_restore() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    ctx.create_symlink("new.txt", "farm/link")?;
+    ctx.run_slinky(&["farm", "snapshot", "farm.json"])
+        .success();
+    let link = ctx.create_symlink("old.txt", "copy/link")?;
+
+    ctx.run_slinky(&["restore", "farm.json", "--into", "copy", "--force"])
+        .success();
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "new.txt");
+
+    ctx.run_slinky(&["undo"])
+        .success();
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "old.txt");
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_non_utf8_name() -> Result<(), Box<dyn std::error::Error>> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let ctx = TestContext::new()?;
+    let link = ctx.path().join(OsStr::from_bytes(b"l\xff"));
+    std::os::unix::fs::symlink("x", &link)?;
+
+    ctx.run_slinky(&["remove"])
+        .success();
+    assert!(fs::symlink_metadata(&link).is_err());
+
+    ctx.run_slinky(&["undo"])
+        .success();
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "x");
+
+    Ok(())
+}
+
+#[test]
+fn test_unjournaled_change_not_made() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = TestContext::new()?;
+    let link = ctx.create_symlink("target.txt", "link.txt")?;
+    let blocked = ctx.create_file("state", "not a directory")?;
+
+    let mut cmd = assert_cmd::Command::from_std(ctx.slinky_cmd());
+    cmd.env("SLINKY_STATE_DIR", blocked.join("slinky"));
+    cmd.args(["remove"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("not changed, since it could not be journaled"));
+    assert_eq!(fs::read_link(&link)?.to_str().unwrap(), "target.txt");
+
+    Ok(())
+}